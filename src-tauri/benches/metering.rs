@@ -0,0 +1,120 @@
+//! Per-block cost of the metering thread, for sizing the hardware it runs on.
+//! Blocks are the preferred callback size at 48 kHz.
+
+use std::{f32::consts::PI, hint::black_box, thread, time::Duration};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use decibender::audio::{
+    ring, Aggregation, Bands, DirectionFinder, EchoCanceller, FilterMode, FilterStage, Input,
+    Meter, MeterSettings, Weighting,
+};
+
+const SAMPLE_RATE: u32 = 48_000;
+const BLOCK_FRAMES: usize = 4000;
+
+/// Noise with a tone on top, loud enough that nothing is skipped as silence
+fn signal(frames: usize, seed: u32) -> Vec<f32> {
+    let mut state = seed.max(1);
+    (0..frames)
+        .map(|i| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            let noise = state as f32 / u32::MAX as f32 - 0.5;
+            let tone = (2.0 * PI * 440.0 * i as f32 / SAMPLE_RATE as f32).sin();
+            0.1 * noise + 0.2 * tone
+        })
+        .collect()
+}
+
+fn settings() -> MeterSettings {
+    MeterSettings {
+        rms_seconds: 5.0,
+        weighting: Weighting::A,
+        impulse_level: -3.0,
+        bands: Bands::ThirdOctave,
+        aggregation: Aggregation::Max,
+        mic_weights: Vec::new(),
+        mic_spacing: 0.2,
+        filters: Vec::new(),
+        extra_mics: Vec::new(),
+    }
+}
+
+fn meter(c: &mut Criterion) {
+    let settings = settings();
+    let block = signal(BLOCK_FRAMES, 1);
+    let crowd = signal(BLOCK_FRAMES, 2);
+
+    let mut meter = Meter::new(SAMPLE_RATE, settings.weighting);
+    c.bench_function("meter", |b| {
+        b.iter(|| meter.process(black_box(&block), None, &settings));
+    });
+
+    let mut meter = Meter::new(SAMPLE_RATE, settings.weighting);
+    c.bench_function("meter with crowd", |b| {
+        b.iter(|| meter.process(black_box(&block), Some(&crowd), &settings));
+    });
+
+    let filtered = MeterSettings {
+        filters: vec![
+            FilterStage {
+                mode: FilterMode::Notch,
+                cutoff: 50.0,
+                resonance: 0.9,
+            },
+            FilterStage {
+                mode: FilterMode::LowPass,
+                cutoff: 8000.0,
+                resonance: 0.0,
+            },
+        ],
+        ..settings
+    };
+    let mut meter = Meter::new(SAMPLE_RATE, filtered.weighting);
+    c.bench_function("meter with filters", |b| {
+        b.iter(|| meter.process(black_box(&block), None, &filtered));
+    });
+}
+
+fn echo_canceller(c: &mut Criterion) {
+    let mic = signal(BLOCK_FRAMES, 3);
+    let reference = signal(BLOCK_FRAMES, 4);
+    let mut echo = EchoCanceller::new(SAMPLE_RATE);
+    let mut residual = Vec::new();
+    c.bench_function("echo canceller", |b| {
+        b.iter(|| {
+            echo.push_reference(&reference, SAMPLE_RATE);
+            echo.process(black_box(&mic), &mut residual);
+        });
+    });
+}
+
+fn direction_finder(c: &mut Criterion) {
+    let left = signal(BLOCK_FRAMES, 5);
+    let right = signal(BLOCK_FRAMES, 6);
+    let mut direction = DirectionFinder::new(SAMPLE_RATE);
+    c.bench_function("direction finder", |b| {
+        b.iter(|| direction.process(black_box(&left), black_box(&right), 0.2));
+    });
+}
+
+fn ring_buffer(c: &mut Criterion) {
+    let interleaved = signal(2 * BLOCK_FRAMES, 7);
+    let (mut writer, mut reader) = ring(Input::Mic(0), SAMPLE_RATE, 2, None, thread::current());
+    c.bench_function("ring write and read", |b| {
+        b.iter(|| {
+            writer.write(interleaved.iter().copied(), Duration::ZERO);
+            black_box(reader.read().map(|block| block.samples.len()))
+        });
+    });
+}
+
+criterion_group!(
+    benches,
+    meter,
+    echo_canceller,
+    direction_finder,
+    ring_buffer
+);
+criterion_main!(benches);
@@ -1,169 +1,585 @@
 use std::{
-    collections::VecDeque,
-    f32::consts::PI,
-    fs::File,
-    io::BufReader,
-    path::PathBuf,
-    sync::mpsc::{self, Sender},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc,
+    },
     thread,
-    time::Instant,
+    time::{Duration, Instant},
 };
 
-use anyhow::Context;
-use cpal::{
-    traits::{DeviceTrait, HostTrait, StreamTrait},
-    BufferSize,
+use clip::ClipRecorder;
+use health::HealthMonitor;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast, watch};
+
+mod aggregation;
+mod biquad;
+mod capture;
+mod clip;
+mod direction;
+mod echo;
+mod filter;
+mod health;
+mod histogram;
+mod lufs;
+mod meter;
+mod noise_floor;
+mod peak;
+mod playback;
+mod replay;
+mod ring;
+mod spectrum;
+mod time_weighting;
+mod vad;
+mod weighting;
+
+pub use aggregation::{aggregate, Aggregation, MicLevel};
+pub use capture::{
+    list_input_devices, Channels, CpalSource, ExtraMic, Input, InputConfigInfo, InputDeviceInfo,
+    InputDevices, MicStatus,
 };
-use rodio::{Decoder, OutputStream, Sink, Source};
-use tokio::sync::watch;
+pub use clip::{ClipRequest, ClipSettings};
+pub use direction::{Direction, DirectionFinder, Side};
+pub use echo::EchoCanceller;
+pub use filter::{FilterMode, FilterStage};
+pub use health::{MicHealth, Problem, SampleStats};
+pub use histogram::LevelHistogram;
+pub use lufs::Lufs;
+pub use meter::Meter;
+pub use peak::Peak;
+pub use playback::{list_output_devices, Completion, OutputDeviceInfo, PlayHandle, Playback};
+pub use replay::FileSource;
+pub use ring::{ring, Block, RingReader, RingWriter, Rings};
+pub use spectrum::{Band, Bands, Spectrum};
+pub use time_weighting::TimeWeighted;
+pub use vad::{Activity, VoiceActivity};
+pub use weighting::Weighting;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MeterSettings {
+    pub rms_seconds: f32,
+    pub weighting: Weighting,
+    /// True-peak level in dBFS above which an [`Impulse`] is reported
+    pub impulse_level: f32,
+    #[serde(default)]
+    pub bands: Bands,
+    /// How the main and extra microphones are combined
+    #[serde(default)]
+    pub aggregation: Aggregation,
+    /// For [`Aggregation::Weighted`], by microphone with the main one first, 1 when missing
+    #[serde(default)]
+    pub mic_weights: Vec<f32>,
+    /// Distance between the left and right microphone of a stereo input in meters,
+    /// to find the [`Direction`] of the loudest source
+    #[serde(default = "default_mic_spacing")]
+    pub mic_spacing: f32,
+    /// Applied to every microphone before anything is measured, e.g. to remove mains hum
+    #[serde(default)]
+    pub filters: Vec<FilterStage>,
+    /// By extra microphone, in the order of [`InputDevices::extra_mics`]
+    #[serde(default)]
+    pub extra_mics: Vec<MicSettings>,
+}
+
+/// How an extra microphone is measured where it differs from the main one
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct MicSettings {
+    /// `None` for [`MeterSettings::weighting`]
+    #[serde(default)]
+    pub weighting: Option<Weighting>,
+    /// Instead of [`MeterSettings::filters`], `None` for those
+    #[serde(default)]
+    pub filters: Option<Vec<FilterStage>>,
+}
 
-const BUFFER_SIZE: u32 = 4000;
+impl MeterSettings {
+    /// Of every extra microphone, in the order of [`InputDevices::extra_mics`]
+    fn for_extra_mics(&self) -> Vec<MeterSettings> {
+        self.extra_mics
+            .iter()
+            .map(|mic| MeterSettings {
+                weighting: mic.weighting.unwrap_or(self.weighting),
+                filters: mic.filters.clone().unwrap_or_else(|| self.filters.clone()),
+                ..self.clone()
+            })
+            .collect()
+    }
+}
+
+fn default_mic_spacing() -> f32 {
+    0.2
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Loudness {
+    /// Equivalent continuous level (Leq) over `rms_seconds`, in weighted dBFS or dB SPL
+    pub loudness: f32,
+    /// Whether levels are calibrated to dB SPL, see [`Loudness::calibrated`]
+    pub spl: bool,
+    /// Like `loudness`, but without what the speakers play. Only measured while
+    /// an echo reference is delivering samples.
+    pub crowd: Option<f32>,
+    pub voice_activity: VoiceActivity,
+    /// Ambient level of the main microphone over the last minutes, in the same weighting
+    /// as `loudness`. Unknown for the first seconds.
+    pub noise_floor: Option<f32>,
+    /// Of the dominant source, only with a stereo input
+    pub direction: Option<Direction>,
+    pub weighting: Weighting,
+    pub time_weighted: TimeWeighted,
+    pub lufs: Lufs,
+    /// Of the latest block only, unweighted
+    pub peak: Peak,
+    /// Too big to send with every loudness update, emitted on its own
+    #[serde(skip)]
+    pub spectrum: Spectrum,
+    /// Measuring is paused because our own sounds are playing, levels are from before
+    pub gated: bool,
+}
+
+/// A sudden bang, reported once each time the true-peak crosses `impulse_level`
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Impulse {
+    pub true_peak: f32,
+    pub crest_factor: f32,
+}
+
+/// Reports an [`Impulse`] when the true-peak rises above the impulse level
+#[derive(Default)]
+struct ImpulseDetector {
+    above_impulse_level: bool,
+}
+
+impl ImpulseDetector {
+    fn detect(&mut self, peak: &Peak, impulse_level: f32) -> Option<Impulse> {
+        let was_above = std::mem::replace(
+            &mut self.above_impulse_level,
+            peak.true_peak > impulse_level,
+        );
+        (self.above_impulse_level && !was_above).then_some(Impulse {
+            true_peak: peak.true_peak,
+            crest_factor: peak.crest_factor,
+        })
+    }
+}
+
+/// Which measurement thresholds are compared against
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Metric {
+    #[default]
+    #[serde(alias = "Rms")]
+    Leq,
+    Fast,
+    Slow,
+    Impulse,
+    MomentaryLufs,
+    ShortTermLufs,
+    /// Unweighted level of the bass bands
+    Bass,
+    /// Unweighted level of the speech bands
+    Voice,
+    /// Leq with the echo of what the speakers play cancelled, plain Leq without a reference
+    Crowd,
+    /// Leq of speech only, see [`VoiceActivity`]
+    Speech,
+}
+
+impl Metric {
+    /// LUFS stay relative to full scale, calibrated or not
+    pub fn is_lufs(self) -> bool {
+        matches!(self, Metric::MomentaryLufs | Metric::ShortTermLufs)
+    }
 
+    /// The noise floor is tracked in the weighted level, LUFS and single bands are in other units
+    pub fn has_noise_floor(self) -> bool {
+        !self.is_lufs() && !matches!(self, Metric::Bass | Metric::Voice)
+    }
+}
+
+impl Loudness {
+    fn silent(weighting: Weighting) -> Self {
+        Self {
+            loudness: -60.0,
+            spl: false,
+            crowd: None,
+            voice_activity: VoiceActivity::default(),
+            noise_floor: None,
+            direction: None,
+            weighting,
+            time_weighted: TimeWeighted::default(),
+            lufs: Lufs::default(),
+            peak: Peak::default(),
+            spectrum: Spectrum::default(),
+            gated: false,
+        }
+    }
+
+    /// Shifts the acoustic levels from dBFS to dB SPL by the microphone's `offset`.
+    /// LUFS and peaks stay relative to full scale, they're about the signal.
+    pub fn calibrated(self, offset: f32) -> Self {
+        Self {
+            loudness: self.loudness + offset,
+            spl: true,
+            crowd: self.crowd.map(|crowd| crowd + offset),
+            noise_floor: self.noise_floor.map(|floor| floor + offset),
+            voice_activity: VoiceActivity {
+                speech_loudness: self.voice_activity.speech_loudness + offset,
+                ..self.voice_activity
+            },
+            time_weighted: self.time_weighted.offset(offset),
+            spectrum: self.spectrum.calibrated(offset),
+            ..self
+        }
+    }
+
+    pub fn get(&self, metric: Metric) -> f32 {
+        match metric {
+            Metric::Leq => self.loudness,
+            Metric::Fast => self.time_weighted.fast,
+            Metric::Slow => self.time_weighted.slow,
+            Metric::Impulse => self.time_weighted.impulse,
+            Metric::MomentaryLufs => self.lufs.momentary,
+            Metric::ShortTermLufs => self.lufs.short_term,
+            Metric::Bass => self.spectrum.bass(),
+            Metric::Voice => self.spectrum.voice(),
+            Metric::Crowd => self.crowd.unwrap_or(self.loudness),
+            Metric::Speech => self.voice_activity.speech_loudness,
+        }
+    }
+}
+
+/// Where [`watch_loudness`] gets its samples from
+pub trait LoudnessSource: Send + 'static {
+    /// Starts writing samples to rings opened from `rings` until nobody reads them anymore
+    /// or the source runs out, reporting how it's doing on `status_tx`
+    fn start(self: Box<Self>, rings: Rings, status_tx: watch::Sender<MicStatus>);
+}
+
+pub struct Metering {
+    /// Of the main microphone, aggregate with `mic_levels` once each is calibrated
+    pub loudness: watch::Receiver<Loudness>,
+    /// The latest uncalibrated levels of every microphone that's delivering samples
+    pub mic_levels: watch::Receiver<Vec<MicLevel>>,
+    pub impulses: broadcast::Receiver<Impulse>,
+    pub mic_status: watch::Receiver<MicStatus>,
+    /// Of the main microphone
+    pub mic_health: watch::Receiver<MicHealth>,
+    /// Only served while clips are enabled in the clip settings
+    pub clip_requests: mpsc::Sender<ClipRequest>,
+}
+
+/// Measures the loudness of whatever `source` delivers, usually the selected input device.
+/// A [`CpalSource`] is supervised: whenever it dies or its input devices change
+/// it's rebuilt, while `loudness` keeps its receivers the whole time.
 pub fn watch_loudness(
-    mut rms_seconds: watch::Receiver<f32>,
-) -> anyhow::Result<watch::Receiver<f32>> {
-    let device_name = env!("INPUT_DEVICE");
-    let host = cpal::default_host();
-    let mic = host
-        .input_devices()?
-        .find(|d| d.name().is_ok_and(|name| name == device_name))
-        .ok_or_else(|| anyhow::anyhow!("Device {device_name} not found"))?;
-    let mut mic_config = mic.default_input_config()?.config();
-    mic_config.buffer_size = BufferSize::Fixed(BUFFER_SIZE);
-
-    let (tx, rx) = mpsc::channel::<f32>();
-    let mut filter = Filter {
-        mode: FilterMode::HighPass,
-        cutoff: 100.0,
-        resonance: 0.0,
-        ic1eq: 0.0,
-        ic2eq: 0.0,
-        sample_rate: mic_config.sample_rate.0 as f32,
+    settings: watch::Receiver<MeterSettings>,
+    clip_settings: watch::Receiver<ClipSettings>,
+    source: Box<dyn LoudnessSource>,
+) -> Metering {
+    let (readers_tx, readers_rx) = mpsc::channel();
+    let (status_tx, status_rx) = watch::channel(MicStatus::Connecting);
+    let (watch_tx, watch_rx) = watch::channel(Loudness::silent(settings.borrow().weighting));
+    let (impulse_tx, impulse_rx) = broadcast::channel(16);
+    let (mic_levels_tx, mic_levels_rx) = watch::channel(Vec::new());
+    let (health_tx, health_rx) = watch::channel(MicHealth::default());
+    let (clip_requests_tx, clip_requests) = mpsc::channel();
+
+    let current_settings = settings.borrow().clone();
+    let worker = Worker {
+        settings,
+        extra_mic_settings: current_settings.for_extra_mics(),
+        current_settings,
+        meters: Vec::new(),
+        mic_levels: Vec::new(),
+        echo: None,
+        crowd: Vec::new(),
+        direction: None,
+        health: None,
+        clip_settings,
+        clip_requests,
+        clips: None,
+        impulses: ImpulseDetector::default(),
+        gated_until: Instant::now(),
+        watch_tx,
+        impulse_tx,
+        mic_levels_tx,
+        health_tx,
     };
+    let worker = thread::spawn(move || worker.run(&readers_rx));
+    source.start(Rings::new(readers_tx, worker.thread().clone()), status_tx);
 
-    let input_stream = mic.build_input_stream(
-        &mic_config,
-        move |data: &[f32], _| {
-            debug_assert_eq!(data.len(), BUFFER_SIZE as usize);
-            let filtered = data.iter().map(|&x| filter.process(x));
-            let mean_square = filtered.map(|x| x.powi(2) / data.len() as f32).sum::<f32>();
-            if tx.send(mean_square).is_err() {
-                panic!("Failed to send mean square to watch receiver");
-            }
-        },
-        |err| {
-            eprintln!("An error occurred on the input stream: {}", err);
-        },
-        None,
-    )?;
-    input_stream.play()?;
-
-    let (watch_tx, watch_rx) = watch::channel(-60.0);
-
-    thread::spawn(move || {
-        let mut mean_square_buffer = VecDeque::new();
+    Metering {
+        loudness: watch_rx,
+        impulses: impulse_rx,
+        mic_levels: mic_levels_rx,
+        mic_status: status_rx,
+        mic_health: health_rx,
+        clip_requests: clip_requests_tx,
+    }
+}
+
+/// The metering thread, reading every stream's ring and measuring what's in it
+struct Worker {
+    settings: watch::Receiver<MeterSettings>,
+    /// Cloned only when the settings change
+    current_settings: MeterSettings,
+    /// With each extra microphone's own weighting and filters
+    extra_mic_settings: Vec<MeterSettings>,
+    /// By microphone, the main one first
+    meters: Vec<Option<Meter>>,
+    mic_levels: Vec<Option<(MicLevel, Instant)>>,
+    echo: Option<EchoCanceller>,
+    /// What's left of the latest block after echo cancellation, reused between blocks
+    crowd: Vec<f32>,
+    direction: Option<DirectionFinder>,
+    health: Option<HealthMonitor>,
+    clip_settings: watch::Receiver<ClipSettings>,
+    clip_requests: mpsc::Receiver<ClipRequest>,
+    /// Only while clips are enabled
+    clips: Option<ClipRecorder>,
+    impulses: ImpulseDetector,
+    gated_until: Instant,
+    watch_tx: watch::Sender<Loudness>,
+    impulse_tx: broadcast::Sender<Impulse>,
+    mic_levels_tx: watch::Sender<Vec<MicLevel>>,
+    health_tx: watch::Sender<MicHealth>,
+}
+
+impl Worker {
+    fn run(mut self, new_readers: &mpsc::Receiver<RingReader>) {
+        let mut readers: Vec<RingReader> = Vec::new();
         loop {
-            let Ok(mean_square) = rx.recv() else {
-                panic!("Failed to receive mean square from input stream");
-            };
-            mean_square_buffer.push_back(mean_square);
-            let target_len = (mic_config.sample_rate.0 as f32 / BUFFER_SIZE as f32
-                * *rms_seconds.borrow_and_update())
-            .round() as usize;
-            if mean_square_buffer.len() > target_len {
-                mean_square_buffer.drain(..mean_square_buffer.len() - target_len);
+            let mut sources_gone = false;
+            loop {
+                match new_readers.try_recv() {
+                    Ok(reader) => readers.push(reader),
+                    Err(mpsc::TryRecvError::Empty) => break,
+                    Err(mpsc::TryRecvError::Disconnected) => {
+                        sources_gone = true;
+                        break;
+                    }
+                }
+            }
+            // The reference goes first, the microphone blocks are cancelled against it
+            readers.sort_by_key(|reader| reader.input() != Input::Reference);
+
+            let mut idle = true;
+            for reader in &mut readers {
+                while let Some(block) = reader.read() {
+                    idle = false;
+                    self.process(block);
+                }
+            }
+            readers.retain(|reader| !reader.is_finished());
+            if sources_gone && readers.is_empty() {
+                break;
+            }
+            if idle {
+                // Writers wake this up, the timeout is in case a wakeup was missed
+                thread::park_timeout(IDLE_TIMEOUT);
             }
-            let mean_square_avg =
-                mean_square_buffer.iter().copied().sum::<f32>() / mean_square_buffer.len() as f32;
-            let rms = mean_square_avg.sqrt().max(0.0).min(1.0);
-            let decibels = 20.0 * rms.log10();
-            watch_tx.send(decibels).ok();
-        }
-    });
-
-    Ok(watch_rx)
-}
-
-#[allow(dead_code)]
-enum FilterMode {
-    /// Removes frequencies above the cutoff frequency.
-    LowPass,
-    /// Removes frequencies above and below the cutoff frequency.
-    BandPass,
-    /// Removes frequencies below the cutoff frequency.
-    HighPass,
-    /// Removes frequencies around the cutoff frequency.
-    Notch,
-}
-
-struct Filter {
-    mode: FilterMode,
-    cutoff: f32,
-    resonance: f32,
-    ic1eq: f32,
-    ic2eq: f32,
-    sample_rate: f32,
-}
-
-impl Filter {
-    // copied from https://github.com/tesselode/kira/blob/main/crates/kira/src/effect/filter.rs
-    fn process(&mut self, sample: f32) -> f32 {
-        let g = (PI * (self.cutoff / self.sample_rate)).tan();
-        let k = 2.0 - (1.9 * self.resonance.min(1.0).max(0.0));
-        let a1 = 1.0 / (1.0 + (g * (g + k)));
-        let a2 = g * a1;
-        let a3 = g * a2;
-        let v3 = sample - self.ic2eq;
-        let v1 = (self.ic1eq * (a1 as f32)) + (v3 * (a2 as f32));
-        let v2 = self.ic2eq + (self.ic1eq * (a2 as f32)) + (v3 * (a3 as f32));
-        self.ic1eq = (v1 * 2.0) - self.ic1eq;
-        self.ic2eq = (v2 * 2.0) - self.ic2eq;
-        match self.mode {
-            FilterMode::LowPass => v2,
-            FilterMode::BandPass => v1,
-            FilterMode::HighPass => sample - v1 * (k as f32) - v2,
-            FilterMode::Notch => sample - v1 * (k as f32),
         }
+        log::error!("Loudness source stopped, loudness will no longer update");
     }
-}
 
-pub fn play_file(file_path: &PathBuf) -> anyhow::Result<PlayHandle> {
-    let file = BufReader::new(
-        File::open(file_path).with_context(|| format!("Failed to open file {file_path:?}"))?,
-    );
-    let source =
-        Decoder::new(file).with_context(|| format!("Failed to decode file {file_path:?}"))?;
-    let total_duration = source
-        .total_duration()
-        .ok_or_else(|| anyhow::anyhow!("Failed to get total duration for file {file_path:?}"))?;
-    let (tx, rx) = mpsc::channel();
-    thread::spawn(move || {
-        let Ok((_stream, stream_handle)) = OutputStream::try_default() else {
-            log::error!("Failed to get default output stream");
-            return;
+    fn process(&mut self, block: &Block) {
+        let Block {
+            samples,
+            sample_rate,
+            input,
+            stereo,
+            stats,
+            ..
+        } = block;
+        let sample_rate = *sample_rate;
+        let mic = match *input {
+            Input::Mic(mic) => mic,
+            Input::Reference => {
+                // At the microphone's sample rate once it delivers
+                self.echo
+                    .get_or_insert_with(|| EchoCanceller::new(sample_rate))
+                    .push_reference(samples, sample_rate);
+                return;
+            }
         };
-        let Ok(sink) = Sink::try_new(&stream_handle) else {
-            log::error!("Failed to get sink from stream handle");
-            return;
+        if mic == 0 {
+            // Also while gated, our own sounds may be what the incident was about
+            self.record(samples, sample_rate);
+        }
+        // Also while gated, to keep the reference lined up with the microphone
+        let has_crowd = match self.echo.as_mut() {
+            Some(echo) if mic == 0 && echo.is_active() => {
+                if echo.sample_rate() != sample_rate {
+                    // Starts over, the reference is resampled to the microphone from now on
+                    *echo = EchoCanceller::new(sample_rate);
+                }
+                echo.process(samples, &mut self.crowd);
+                true
+            }
+            _ => false,
         };
-        sink.append(source);
-        // we expect the sender to be dropped
-        rx.recv_timeout(total_duration).ok();
-    });
-    Ok(PlayHandle {
-        expect_done_at: Instant::now() + total_duration,
-        _tx: tx,
-    })
+        if self
+            .direction
+            .as_ref()
+            .is_some_and(|direction| direction.sample_rate() != sample_rate)
+        {
+            self.direction = None;
+        }
+
+        if PLAYBACKS.load(Ordering::Relaxed) > 0 {
+            self.gated_until = Instant::now() + PLAYBACK_TAIL;
+        }
+        if self.gated_until > Instant::now() {
+            // The mic hears our own sounds, keep the levels from before they started
+            self.watch_tx
+                .send_if_modified(|loudness| !std::mem::replace(&mut loudness.gated, true));
+            return;
+        }
+        if mic == 0 {
+            self.check_health(stats, sample_rate, samples.len());
+        }
+        if stats.silent || stats.invalid > 0 {
+            // A dead or broken input, not a quiet room. The levels from before are kept
+            // instead of dropping to minus infinity, the health check reports the input.
+            return;
+        }
+        if self.settings.has_changed().unwrap_or(false) {
+            self.current_settings = self.settings.borrow_and_update().clone();
+            self.extra_mic_settings = self.current_settings.for_extra_mics();
+        }
+        let settings = mic
+            .checked_sub(1)
+            .and_then(|extra| self.extra_mic_settings.get(extra))
+            .unwrap_or(&self.current_settings);
+        if self.meters.len() <= mic {
+            self.meters.resize_with(mic + 1, || None);
+            self.mic_levels.resize(mic + 1, None);
+        }
+        let meter = &mut self.meters[mic];
+        if meter
+            .as_ref()
+            .is_some_and(|meter| meter.sample_rate() != sample_rate)
+        {
+            // A different device was opened, start measuring from scratch
+            *meter = None;
+        }
+        let meter = meter.get_or_insert_with(|| Meter::new(sample_rate, settings.weighting));
+        let crowd = has_crowd.then_some(self.crowd.as_slice());
+        let mut loudness = meter.process(samples, crowd, settings);
+        loudness.direction = stereo.as_ref().and_then(|[left, right]| {
+            self.direction
+                .get_or_insert_with(|| DirectionFinder::new(sample_rate))
+                .process(left, right, settings.mic_spacing)
+        });
+        self.mic_levels[mic] = Some((
+            MicLevel {
+                mic,
+                loudness: loudness.loudness,
+                time_weighted: loudness.time_weighted,
+                weighting: settings.weighting,
+                spl: false,
+                weight: settings.mic_weights.get(mic).copied().unwrap_or(1.0),
+            },
+            Instant::now(),
+        ));
+        if mic != 0 {
+            // Extra microphones only contribute to what the main one reports
+            return;
+        }
+
+        if let Some(impulse) = self.impulses.detect(&loudness.peak, settings.impulse_level) {
+            self.impulse_tx.send(impulse).ok();
+        }
+
+        // Aggregated once every microphone is calibrated by its own offset
+        self.mic_levels_tx
+            .send_replace(current_levels(&self.mic_levels));
+        self.watch_tx.send(loudness).ok();
+    }
+
+    fn record(&mut self, samples: &[f32], sample_rate: u32) {
+        let settings = *self.clip_settings.borrow();
+        if !settings.enabled
+            || self
+                .clips
+                .as_ref()
+                .is_some_and(|clips| clips.sample_rate() != sample_rate)
+        {
+            // Nothing is kept in memory while clips are off
+            self.clips = None;
+        }
+        if settings.enabled {
+            let clips = self
+                .clips
+                .get_or_insert_with(|| ClipRecorder::new(sample_rate));
+            while let Ok(request) = self.clip_requests.try_recv() {
+                clips.start(request, &settings);
+            }
+            clips.push(samples, &settings);
+        } else {
+            while let Ok(request) = self.clip_requests.try_recv() {
+                request
+                    .done
+                    .send(Err(anyhow::anyhow!("Clips are turned off")))
+                    .ok();
+            }
+        }
+    }
+
+    fn check_health(&mut self, stats: &SampleStats, sample_rate: u32, frames: usize) {
+        if self
+            .health
+            .as_ref()
+            .is_some_and(|health| health.sample_rate() != sample_rate)
+        {
+            self.health = None;
+        }
+        let health = self
+            .health
+            .get_or_insert_with(|| HealthMonitor::new(sample_rate))
+            .push(stats, frames);
+        self.health_tx.send_if_modified(|current| {
+            let changed = current != health;
+            if changed {
+                current.clone_from(health);
+            }
+            changed
+        });
+    }
 }
 
-/// To stop playback, drop the handle
-pub struct PlayHandle {
-    expect_done_at: Instant,
-    _tx: Sender<()>,
+/// Microphones that stopped delivering, e.g. unplugged or disabled, are left out
+fn current_levels(mic_levels: &[Option<(MicLevel, Instant)>]) -> Vec<MicLevel> {
+    mic_levels
+        .iter()
+        .flatten()
+        .filter(|(_, at)| at.elapsed() < MIC_TIMEOUT)
+        .map(|&(level, _)| level)
+        .collect()
+}
+
+/// Extra microphones that haven't delivered for this long are left out
+const MIC_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Sounds currently audible through [`Playback`]
+static PLAYBACKS: AtomicUsize = AtomicUsize::new(0);
+/// How long the room keeps echoing after our own sounds stop
+const PLAYBACK_TAIL: Duration = Duration::from_millis(500);
+/// How long the metering thread sleeps when no ring has anything to read
+const IDLE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Counts as playing in [`PLAYBACKS`] while alive
+struct Playing;
+
+impl Playing {
+    fn start() -> Self {
+        PLAYBACKS.fetch_add(1, Ordering::Relaxed);
+        Self
+    }
 }
 
-impl PlayHandle {
-    pub fn expect_done_at(&self) -> Instant {
-        self.expect_done_at
+impl Drop for Playing {
+    fn drop(&mut self) {
+        PLAYBACKS.fetch_sub(1, Ordering::Relaxed);
     }
 }
@@ -0,0 +1,114 @@
+use serde::{Deserialize, Serialize};
+
+use super::{TimeWeighted, Weighting};
+
+/// How the levels of several microphones are combined into one
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Aggregation {
+    /// The loudest corner counts
+    #[default]
+    Max,
+    /// As if all microphones were one, with the power averaged over the room
+    MeanPower,
+    /// Ignores a single microphone next to the speakers or a shouting guest
+    Median,
+    /// Mean power, with each microphone weighted by `mic_weights`
+    Weighted,
+}
+
+impl Aggregation {
+    /// Combines levels in dB, given as (level, weight)
+    pub fn combine(self, levels: &[(f32, f32)]) -> f32 {
+        let power = |level: f32| 10_f32.powf(level / 10.0);
+        match self {
+            Aggregation::Max => levels
+                .iter()
+                .map(|&(level, _)| level)
+                .fold(f32::NEG_INFINITY, f32::max),
+            Aggregation::MeanPower if levels.is_empty() => f32::NEG_INFINITY,
+            Aggregation::MeanPower => {
+                let sum = levels.iter().map(|&(level, _)| power(level)).sum::<f32>();
+                10.0 * (sum / levels.len() as f32).log10()
+            }
+            Aggregation::Median => {
+                let mut sorted = levels.iter().map(|&(level, _)| level).collect::<Vec<_>>();
+                sorted.sort_by(f32::total_cmp);
+                match sorted.len() {
+                    0 => f32::NEG_INFINITY,
+                    len if len % 2 == 1 => sorted[len / 2],
+                    len => Aggregation::MeanPower
+                        .combine(&[(sorted[len / 2 - 1], 1.0), (sorted[len / 2], 1.0)]),
+                }
+            }
+            Aggregation::Weighted => {
+                let total_weight = levels.iter().map(|&(_, weight)| weight).sum::<f32>();
+                if total_weight <= 0.0 {
+                    // No weights set yet, or every microphone muted
+                    return Aggregation::MeanPower.combine(levels);
+                }
+                let sum = levels
+                    .iter()
+                    .map(|&(level, weight)| weight * power(level))
+                    .sum::<f32>();
+                10.0 * (sum / total_weight).log10()
+            }
+        }
+    }
+}
+
+/// Levels of a single microphone, before they're aggregated
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct MicLevel {
+    /// 0 is the main microphone, then the extra ones in order
+    pub mic: usize,
+    pub loudness: f32,
+    pub time_weighted: TimeWeighted,
+    /// Extra microphones can have their own
+    pub weighting: Weighting,
+    /// Whether levels are calibrated to dB SPL by the microphone's own offset
+    pub spl: bool,
+    #[serde(skip)]
+    pub weight: f32,
+}
+
+impl MicLevel {
+    pub fn calibrated(self, offset: f32) -> Self {
+        Self {
+            loudness: self.loudness + offset,
+            time_weighted: self.time_weighted.offset(offset),
+            spl: true,
+            ..self
+        }
+    }
+}
+
+/// Combines every level that's measured per microphone
+pub fn aggregate(aggregation: Aggregation, mics: &[MicLevel]) -> (f32, TimeWeighted) {
+    let combine = |level: fn(&MicLevel) -> f32| {
+        let levels = mics
+            .iter()
+            .map(|mic| (level(mic), mic.weight))
+            .collect::<Vec<_>>();
+        aggregation.combine(&levels)
+    };
+    (
+        combine(|mic| mic.loudness),
+        TimeWeighted {
+            fast: combine(|mic| mic.time_weighted.fast),
+            slow: combine(|mic| mic.time_weighted.slow),
+            impulse: combine(|mic| mic.time_weighted.impulse),
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn weighted_without_weights_is_mean_power() {
+        let levels = [(60.0, 0.0), (60.0, 0.0)];
+        assert!((Aggregation::Weighted.combine(&levels) - 60.0).abs() < 1e-3);
+        assert_eq!(Aggregation::Weighted.combine(&[]), f32::NEG_INFINITY);
+    }
+}
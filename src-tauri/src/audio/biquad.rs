@@ -0,0 +1,79 @@
+/// A second order section in transposed direct form II
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Biquad {
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+    z1: f64,
+    z2: f64,
+}
+
+impl Biquad {
+    pub fn new([b0, b1, b2]: [f64; 3], [a1, a2]: [f64; 2]) -> Self {
+        Self {
+            b0,
+            b1,
+            b2,
+            a1,
+            a2,
+            z1: 0.0,
+            z2: 0.0,
+        }
+    }
+
+    /// Section with the given real zeros and poles in the z-plane
+    pub fn from_roots(zeros: [f64; 2], poles: [f64; 2]) -> Self {
+        Self::new(
+            [1.0, -(zeros[0] + zeros[1]), zeros[0] * zeros[1]],
+            [-(poles[0] + poles[1]), poles[0] * poles[1]],
+        )
+    }
+
+    /// Second order Butterworth low-pass, from the audio EQ cookbook
+    pub fn low_pass(cutoff: f64, sample_rate: f64) -> Self {
+        let (alpha, cos) = cookbook(cutoff, sample_rate);
+        let a0 = 1.0 + alpha;
+        let b = 0.5 * (1.0 - cos) / a0;
+        Self::new([b, 2.0 * b, b], [-2.0 * cos / a0, (1.0 - alpha) / a0])
+    }
+
+    /// Second order Butterworth high-pass, from the audio EQ cookbook
+    pub fn high_pass(cutoff: f64, sample_rate: f64) -> Self {
+        let (alpha, cos) = cookbook(cutoff, sample_rate);
+        let a0 = 1.0 + alpha;
+        let b = 0.5 * (1.0 + cos) / a0;
+        Self::new([b, -2.0 * b, b], [-2.0 * cos / a0, (1.0 - alpha) / a0])
+    }
+
+    pub fn scale(&mut self, gain: f64) {
+        self.b0 *= gain;
+        self.b1 *= gain;
+        self.b2 *= gain;
+    }
+
+    /// Magnitude response at `omega` radians per sample
+    pub fn gain_at(&self, omega: f64) -> f64 {
+        let (sin1, cos1) = omega.sin_cos();
+        let (sin2, cos2) = (2.0 * omega).sin_cos();
+        let numerator =
+            (self.b0 + self.b1 * cos1 + self.b2 * cos2).hypot(self.b1 * sin1 + self.b2 * sin2);
+        let denominator =
+            (1.0 + self.a1 * cos1 + self.a2 * cos2).hypot(self.a1 * sin1 + self.a2 * sin2);
+        numerator / denominator
+    }
+
+    pub fn process(&mut self, x: f64) -> f64 {
+        let y = self.b0 * x + self.z1;
+        self.z1 = self.b1 * x - self.a1 * y + self.z2;
+        self.z2 = self.b2 * x - self.a2 * y;
+        y
+    }
+}
+
+/// `alpha` and `cos(w0)` for a Butterworth Q
+fn cookbook(cutoff: f64, sample_rate: f64) -> (f64, f64) {
+    let (sin, cos) = (std::f64::consts::TAU * cutoff / sample_rate).sin_cos();
+    (sin * std::f64::consts::FRAC_1_SQRT_2, cos)
+}
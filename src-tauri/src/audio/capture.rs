@@ -0,0 +1,466 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use cpal::{
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+    BufferSize, Device, FromSample, InputCallbackInfo, SampleFormat, SizedSample, Stream,
+    StreamConfig, StreamError, StreamInstant, SupportedBufferSize,
+};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    runtime::Handle,
+    sync::{mpsc, watch},
+    time::{interval_at, sleep, Instant},
+};
+
+use super::{LoudnessSource, Rings};
+
+/// Preferred callback size in frames, the device default is used when it isn't supported
+const BUFFER_SIZE: u32 = 4000;
+/// How long an open stream may go without delivering data before it's considered dead
+const STALL_TIMEOUT: Duration = Duration::from_secs(3);
+const MIN_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Default)]
+pub struct InputDevices {
+    /// The device to listen on, `None` means the default input
+    pub preferred: Option<String>,
+    /// Tried when the preferred device can't be opened, before the default input
+    pub fallback: Option<String>,
+    pub channels: Channels,
+    /// Output monitor capturing what the speakers play, for echo cancellation
+    pub echo_reference: Option<String>,
+    /// More microphones around the room, measured alongside this one
+    pub extra_mics: Vec<ExtraMic>,
+}
+
+/// Unlike the main microphone, an extra one has no fallback
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ExtraMic {
+    pub name: String,
+    #[serde(default)]
+    pub channels: Channels,
+    pub enabled: bool,
+}
+
+/// Which stream a [`Block`](super::Block) comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Input {
+    /// 0 is the main microphone, then the extra ones in order
+    Mic(usize),
+    /// The echo reference
+    Reference,
+}
+
+/// How a multi-channel input is turned into the single signal that gets measured
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize)]
+pub enum Channels {
+    /// Average all channels
+    #[default]
+    Mix,
+    /// Only measure this channel, counting from 0
+    Select(u16),
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status")]
+pub enum MicStatus {
+    Connecting,
+    Connected {
+        device: String,
+    },
+    Disconnected {
+        error: String,
+        attempt: u32,
+        retry_in_ms: u64,
+    },
+    /// The source ran out for good, e.g. a replayed recording ended
+    Finished {
+        error: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct InputDeviceInfo {
+    pub name: String,
+    pub is_default: bool,
+    pub configs: Vec<InputConfigInfo>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct InputConfigInfo {
+    pub channels: u16,
+    pub min_sample_rate: u32,
+    pub max_sample_rate: u32,
+    pub min_buffer_size: Option<u32>,
+    pub max_buffer_size: Option<u32>,
+    pub sample_format: String,
+}
+
+pub fn list_input_devices() -> anyhow::Result<Vec<InputDeviceInfo>> {
+    let host = cpal::default_host();
+    let default_name = host.default_input_device().and_then(|d| d.name().ok());
+    let mut devices = Vec::new();
+    for device in host.input_devices()? {
+        let Ok(name) = device.name() else {
+            continue;
+        };
+        let configs = match device.supported_input_configs() {
+            Ok(configs) => configs
+                .map(|config| {
+                    let (min_buffer_size, max_buffer_size) = match *config.buffer_size() {
+                        SupportedBufferSize::Range { min, max } => (Some(min), Some(max)),
+                        SupportedBufferSize::Unknown => (None, None),
+                    };
+                    InputConfigInfo {
+                        channels: config.channels(),
+                        min_sample_rate: config.min_sample_rate().0,
+                        max_sample_rate: config.max_sample_rate().0,
+                        min_buffer_size,
+                        max_buffer_size,
+                        sample_format: config.sample_format().to_string(),
+                    }
+                })
+                .collect(),
+            Err(e) => {
+                log::warn!("Failed to get supported configs for input device {name}: {e}");
+                Vec::new()
+            }
+        };
+        devices.push(InputDeviceInfo {
+            is_default: default_name.as_ref() == Some(&name),
+            name,
+            configs,
+        });
+    }
+    Ok(devices)
+}
+
+/// The live microphone, kept open by [`spawn_supervisor`]
+pub struct CpalSource(pub watch::Receiver<InputDevices>);
+
+impl LoudnessSource for CpalSource {
+    fn start(self: Box<Self>, rings: Rings, status_tx: watch::Sender<MicStatus>) {
+        spawn_supervisor(self.0, rings, status_tx);
+    }
+}
+
+enum Outcome {
+    DevicesChanged,
+    Closed,
+    Failed(anyhow::Error),
+}
+
+/// Keeps an input stream open for as long as `input_devices` has a sender,
+/// reopening it with backoff whenever it errors out or stops delivering data.
+fn spawn_supervisor(
+    mut input_devices: watch::Receiver<InputDevices>,
+    rings: Rings,
+    status_tx: watch::Sender<MicStatus>,
+) {
+    let runtime = Handle::current();
+
+    // cpal streams can't be moved between threads, so this thread owns whichever one is active
+    thread::spawn(move || {
+        let mut attempt = 0;
+        loop {
+            let devices = input_devices.borrow_and_update().clone();
+            let (error_tx, mut error_rx) = mpsc::unbounded_channel();
+            let callbacks = Arc::new(AtomicUsize::new(0));
+            let handlers = StreamHandlers {
+                rings: rings.clone(),
+                error_tx,
+                callbacks: callbacks.clone(),
+                input: Input::Mic(0),
+            };
+            let error = match open_any(&devices, &handlers) {
+                Ok((input_stream, device)) => {
+                    attempt = 0;
+                    status_tx.send_replace(MicStatus::Connected { device });
+                    // Stalls are only watched for on the main microphone
+                    let secondary_handlers = |input| StreamHandlers {
+                        input,
+                        callbacks: Arc::new(AtomicUsize::new(0)),
+                        ..handlers.clone()
+                    };
+                    let reference_stream = devices.echo_reference.as_ref().and_then(|name| {
+                        open_named(name, Channels::Mix, &secondary_handlers(Input::Reference))
+                            .inspect(|_| log::info!("Cancelling echo captured from {name}"))
+                            .inspect_err(|e| {
+                                log::warn!("Failed to open echo reference {name}, not cancelling echo: {e}");
+                            })
+                            .ok()
+                            .map(|stream| (Input::Reference, stream))
+                    });
+                    let mut secondary_streams = reference_stream
+                        .into_iter()
+                        .chain(
+                            devices
+                                .extra_mics
+                                .iter()
+                                .enumerate()
+                                .filter(|(_, mic)| mic.enabled)
+                                .filter_map(|(i, mic)| {
+                                    let name = &mic.name;
+                                    let input = Input::Mic(i + 1);
+                                    open_named(name, mic.channels, &secondary_handlers(input))
+                                        .inspect(|_| log::info!("Also listening on {name}"))
+                                        .inspect_err(|e| {
+                                            log::warn!("Failed to open extra mic {name}: {e}");
+                                        })
+                                        .ok()
+                                        .map(|stream| (input, stream))
+                                }),
+                        )
+                        .collect::<Vec<_>>();
+                    let outcome = runtime.block_on(supervise(
+                        &mut input_devices,
+                        &mut error_rx,
+                        &callbacks,
+                        &mut secondary_streams,
+                    ));
+                    drop(secondary_streams);
+                    drop(input_stream);
+                    match outcome {
+                        Outcome::DevicesChanged => continue,
+                        Outcome::Closed => return,
+                        Outcome::Failed(error) => error,
+                    }
+                }
+                Err(error) => error,
+            };
+
+            let retry_in = MIN_BACKOFF
+                .saturating_mul(2_u32.saturating_pow(attempt))
+                .min(MAX_BACKOFF);
+            attempt += 1;
+            status_tx.send_replace(MicStatus::Disconnected {
+                error: error.to_string(),
+                attempt,
+                retry_in_ms: retry_in.as_millis() as u64,
+            });
+            log::error!(
+                "{:?}",
+                error.context(format!("Input stream failed, retrying in {retry_in:?}"))
+            );
+
+            // Picking another device retries right away instead of waiting out the backoff
+            let changed = runtime.block_on(async {
+                tokio::select! {
+                    changed = input_devices.changed() => Some(changed.is_ok()),
+                    () = sleep(retry_in) => None,
+                }
+            });
+            match changed {
+                Some(true) => attempt = 0,
+                Some(false) => return,
+                None => {}
+            }
+            status_tx.send_replace(MicStatus::Connecting);
+        }
+    });
+}
+
+/// Only the main microphone failing reopens everything, the echo reference or an extra
+/// microphone failing just closes that stream until the devices change
+async fn supervise(
+    input_devices: &mut watch::Receiver<InputDevices>,
+    errors: &mut mpsc::UnboundedReceiver<(Input, StreamError)>,
+    callbacks: &AtomicUsize,
+    secondary_streams: &mut Vec<(Input, Stream)>,
+) -> Outcome {
+    let mut seen = callbacks.load(Ordering::Relaxed);
+    let mut watchdog = interval_at(Instant::now() + STALL_TIMEOUT, STALL_TIMEOUT);
+    loop {
+        tokio::select! {
+            changed = input_devices.changed() => {
+                return if changed.is_ok() {
+                    Outcome::DevicesChanged
+                } else {
+                    Outcome::Closed
+                };
+            }
+            Some((input, error)) = errors.recv() => match error {
+                StreamError::DeviceNotAvailable if input == Input::Mic(0) => {
+                    return Outcome::Failed(error.into());
+                }
+                StreamError::DeviceNotAvailable => {
+                    log::warn!("Closing the {input:?} input stream: {error}");
+                    secondary_streams.retain(|(stream_input, _)| *stream_input != input);
+                }
+                StreamError::BackendSpecific { .. } => {
+                    log::warn!("An error occurred on the {input:?} input stream: {error}");
+                }
+            },
+            _ = watchdog.tick() => {
+                let count = callbacks.load(Ordering::Relaxed);
+                if count == seen {
+                    return Outcome::Failed(anyhow::anyhow!(
+                        "Input stream stalled, no data for {STALL_TIMEOUT:?}"
+                    ));
+                }
+                seen = count;
+            }
+        }
+    }
+}
+
+fn candidate_devices(devices: &InputDevices) -> anyhow::Result<Vec<Device>> {
+    let host = cpal::default_host();
+    let mut candidates = Vec::new();
+    for device_name in [&devices.preferred, &devices.fallback]
+        .into_iter()
+        .flatten()
+    {
+        match host
+            .input_devices()?
+            .find(|d| d.name().is_ok_and(|name| &name == device_name))
+        {
+            Some(device) => candidates.push(device),
+            None => log::warn!("Device {device_name} not found"),
+        }
+    }
+    candidates.extend(host.default_input_device());
+    Ok(candidates)
+}
+
+fn open_any(devices: &InputDevices, handlers: &StreamHandlers) -> anyhow::Result<(Stream, String)> {
+    let mut last_error = anyhow::anyhow!("No input device available");
+    for mic in candidate_devices(devices)? {
+        let name = mic.name().unwrap_or_default();
+        match open_input_stream(&mic, devices.channels, handlers) {
+            Ok(input_stream) => {
+                log::info!("Listening on input device {name}");
+                return Ok((input_stream, name));
+            }
+            Err(e) => {
+                log::warn!("Failed to open input device {name}: {e}");
+                last_error = e;
+            }
+        }
+    }
+    Err(last_error)
+}
+
+/// Opens exactly this device, for streams where any other device would be wrong
+fn open_named(name: &str, channels: Channels, handlers: &StreamHandlers) -> anyhow::Result<Stream> {
+    let device = cpal::default_host()
+        .input_devices()?
+        .find(|d| d.name().is_ok_and(|device_name| device_name == name))
+        .ok_or_else(|| anyhow::anyhow!("Device {name} not found"))?;
+    open_input_stream(&device, channels, handlers)
+}
+
+/// Everything a stream's callbacks report to, shared by every stream the supervisor opens
+#[derive(Clone)]
+struct StreamHandlers {
+    rings: Rings,
+    /// Tagged with `input`, so only the stream that failed is closed
+    error_tx: mpsc::UnboundedSender<(Input, StreamError)>,
+    callbacks: Arc<AtomicUsize>,
+    input: Input,
+}
+
+fn open_input_stream(
+    mic: &Device,
+    channels: Channels,
+    handlers: &StreamHandlers,
+) -> anyhow::Result<Stream> {
+    let supported_config = mic.default_input_config()?;
+    let sample_format = supported_config.sample_format();
+    let mut mic_config = supported_config.config();
+    let channel = match channels {
+        Channels::Mix => None,
+        Channels::Select(channel) if channel < mic_config.channels => Some(usize::from(channel)),
+        Channels::Select(channel) => {
+            log::warn!(
+                "Input has {} channels, can't select channel {channel}, mixing down instead",
+                mic_config.channels
+            );
+            None
+        }
+    };
+
+    let build = |mic_config: &StreamConfig| match sample_format {
+        SampleFormat::I8 => build_input_stream::<i8>(mic, mic_config, channel, handlers.clone()),
+        SampleFormat::I16 => build_input_stream::<i16>(mic, mic_config, channel, handlers.clone()),
+        SampleFormat::I32 => build_input_stream::<i32>(mic, mic_config, channel, handlers.clone()),
+        SampleFormat::I64 => build_input_stream::<i64>(mic, mic_config, channel, handlers.clone()),
+        SampleFormat::U8 => build_input_stream::<u8>(mic, mic_config, channel, handlers.clone()),
+        SampleFormat::U16 => build_input_stream::<u16>(mic, mic_config, channel, handlers.clone()),
+        SampleFormat::U32 => build_input_stream::<u32>(mic, mic_config, channel, handlers.clone()),
+        SampleFormat::U64 => build_input_stream::<u64>(mic, mic_config, channel, handlers.clone()),
+        SampleFormat::F32 => build_input_stream::<f32>(mic, mic_config, channel, handlers.clone()),
+        SampleFormat::F64 => build_input_stream::<f64>(mic, mic_config, channel, handlers.clone()),
+        sample_format => Err(anyhow::anyhow!("Unsupported sample format {sample_format}")),
+    };
+
+    let supports_buffer_size = match *supported_config.buffer_size() {
+        SupportedBufferSize::Range { min, max } => (min..=max).contains(&BUFFER_SIZE),
+        SupportedBufferSize::Unknown => false,
+    };
+    let input_stream = if supports_buffer_size {
+        mic_config.buffer_size = BufferSize::Fixed(BUFFER_SIZE);
+        build(&mic_config).or_else(|e| {
+            log::warn!("{e}, falling back to the default buffer size");
+            mic_config.buffer_size = BufferSize::Default;
+            build(&mic_config)
+        })?
+    } else {
+        build(&mic_config)?
+    };
+    input_stream.play()?;
+
+    Ok(input_stream)
+}
+
+fn build_input_stream<T>(
+    mic: &Device,
+    mic_config: &StreamConfig,
+    channel: Option<usize>,
+    handlers: StreamHandlers,
+) -> anyhow::Result<Stream>
+where
+    T: SizedSample,
+    f32: FromSample<T>,
+{
+    let StreamHandlers {
+        rings,
+        error_tx,
+        callbacks,
+        input,
+    } = handlers;
+    let mut writer = rings.open(
+        input,
+        mic_config.sample_rate.0,
+        mic_config.channels,
+        channel,
+    );
+    let mut started: Option<StreamInstant> = None;
+
+    let input_stream = mic.build_input_stream(
+        mic_config,
+        move |data: &[T], info: &InputCallbackInfo| {
+            callbacks.fetch_add(1, Ordering::Relaxed);
+            let capture = info.timestamp().capture;
+            let captured = capture
+                .duration_since(started.get_or_insert(capture))
+                .unwrap_or_default();
+            // Samples lost when the metering thread falls behind show up as a gap in the timestamps
+            writer.write(data.iter().map(|s| s.to_sample::<f32>()), captured);
+        },
+        move |err| {
+            error_tx.send((input, err)).ok();
+        },
+        None,
+    )?;
+
+    Ok(input_stream)
+}
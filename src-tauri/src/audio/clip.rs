@@ -0,0 +1,156 @@
+use std::{collections::VecDeque, path::PathBuf, thread, time::Duration};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use tokio::sync::oneshot;
+
+/// More history than this isn't kept in memory, however it's configured
+const MAX_PRE_SECONDS: f32 = 60.0;
+const MAX_POST_SECONDS: f32 = 60.0;
+
+/// Whether and how much audio around an incident is saved
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct ClipSettings {
+    /// When off, no audio is kept in memory at all
+    pub enabled: bool,
+    /// Before the incident, up to a minute
+    pub pre_seconds: f32,
+    /// After the incident, up to a minute
+    pub post_seconds: f32,
+    /// The oldest clips are deleted beyond this many
+    pub max_clips: usize,
+}
+
+impl Default for ClipSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            pre_seconds: 10.0,
+            post_seconds: 5.0,
+            max_clips: 50,
+        }
+    }
+}
+
+impl ClipSettings {
+    fn pre_frames(&self, sample_rate: u32) -> usize {
+        frames(self.pre_seconds.clamp(0.0, MAX_PRE_SECONDS), sample_rate)
+    }
+
+    fn post_frames(&self, sample_rate: u32) -> usize {
+        frames(self.post_seconds.clamp(0.0, MAX_POST_SECONDS), sample_rate)
+    }
+}
+
+/// Asks the metering thread to save what the main microphone heard around now
+/// as a WAV file at `path`. `done` gets the length of the clip once it's written.
+pub struct ClipRequest {
+    pub path: PathBuf,
+    pub done: oneshot::Sender<anyhow::Result<Duration>>,
+}
+
+struct PendingClip {
+    path: PathBuf,
+    samples: Vec<f32>,
+    remaining: usize,
+    done: oneshot::Sender<anyhow::Result<Duration>>,
+}
+
+/// Keeps the latest raw samples of the main microphone, so a clip can start before its incident
+pub(super) struct ClipRecorder {
+    sample_rate: u32,
+    history: VecDeque<f32>,
+    pending: Vec<PendingClip>,
+}
+
+impl ClipRecorder {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            history: VecDeque::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// The history starts with what was heard `pre_seconds` ago
+    pub fn start(&mut self, request: ClipRequest, settings: &ClipSettings) {
+        self.pending.push(PendingClip {
+            path: request.path,
+            samples: self.history.iter().copied().collect(),
+            remaining: settings.post_frames(self.sample_rate),
+            done: request.done,
+        });
+        self.finish_complete();
+    }
+
+    pub fn push(&mut self, samples: &[f32], settings: &ClipSettings) {
+        for clip in &mut self.pending {
+            let taken = clip.remaining.min(samples.len());
+            clip.samples.extend_from_slice(&samples[..taken]);
+            clip.remaining -= taken;
+        }
+        self.finish_complete();
+
+        let capacity = settings.pre_frames(self.sample_rate);
+        self.history.extend(samples);
+        if self.history.len() > capacity {
+            self.history.drain(..self.history.len() - capacity);
+        }
+    }
+
+    fn finish_complete(&mut self) {
+        let (complete, pending) = std::mem::take(&mut self.pending)
+            .into_iter()
+            .partition(|clip| clip.remaining == 0);
+        self.pending = pending;
+        for clip in complete {
+            let sample_rate = self.sample_rate;
+            // Not on the metering thread, disks can be slow
+            thread::spawn(move || {
+                let result = write_wav(&clip.path, sample_rate, &clip.samples).map(|()| {
+                    Duration::from_secs_f64(clip.samples.len() as f64 / f64::from(sample_rate))
+                });
+                clip.done.send(result).ok();
+            });
+        }
+    }
+}
+
+impl Drop for ClipRecorder {
+    fn drop(&mut self) {
+        // E.g. the sample rate changed, what's pending would be half at another rate
+        for clip in self.pending.drain(..) {
+            clip.done
+                .send(Err(anyhow::anyhow!(
+                    "The microphone changed while recording"
+                )))
+                .ok();
+        }
+    }
+}
+
+fn frames(seconds: f32, sample_rate: u32) -> usize {
+    (seconds * sample_rate as f32) as usize
+}
+
+/// Mono 16-bit PCM, which every player and [`super::Playback`] can decode
+fn write_wav(path: &PathBuf, sample_rate: u32, samples: &[f32]) -> anyhow::Result<()> {
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(path, spec)
+        .with_context(|| format!("Failed to create {}", path.display()))?;
+    for &sample in samples {
+        writer.write_sample((sample.clamp(-1.0, 1.0) * f32::from(i16::MAX)) as i16)?;
+    }
+    writer
+        .finalize()
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
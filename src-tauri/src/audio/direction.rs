@@ -0,0 +1,300 @@
+use std::{f32::consts::PI, sync::Arc};
+
+use realfft::{num_complex::Complex, ComplexToReal, RealFftPlanner, RealToComplex};
+use serde::{Deserialize, Serialize};
+
+/// Samples per channel correlated at once, zero padded to twice that so lags don't wrap around
+const FRAME_SIZE: usize = 1024;
+const FFT_SIZE: usize = 2 * FRAME_SIZE;
+/// Correlations are averaged over this long before a bearing is estimated
+const AVERAGE_SECONDS: f32 = 0.5;
+/// Only frequencies where voices and music carry energy, noise elsewhere
+/// would get as much weight as the source after PHAT whitening
+const BAND: (f32, f32) = (200.0, 6000.0);
+/// Frames quieter than this in dBFS don't take part
+const SILENCE_LEVEL: f32 = -70.0;
+/// In m/s at room temperature
+const SPEED_OF_SOUND: f32 = 343.0;
+/// Bearings within this many degrees of straight ahead are the center
+const CENTER_DEGREES: f32 = 20.0;
+/// Below this confidence the bearing is too unreliable to point at a side
+const MIN_CONFIDENCE: f32 = 0.1;
+
+/// Where the dominant source is, as seen from the stereo microphone
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Direction {
+    /// -90 degrees is all the way left, 0 straight ahead and 90 all the way right.
+    /// A pair of microphones can't tell front from back.
+    pub bearing: f32,
+    /// How much the correlation peaks, from 0 for diffuse noise to 1 for a single source
+    pub confidence: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Side {
+    Left,
+    Center,
+    Right,
+}
+
+impl Direction {
+    /// `None` when the source can't be located reliably
+    pub fn side(self) -> Option<Side> {
+        if self.confidence < MIN_CONFIDENCE {
+            None
+        } else if self.bearing < -CENTER_DEGREES {
+            Some(Side::Left)
+        } else if self.bearing > CENTER_DEGREES {
+            Some(Side::Right)
+        } else {
+            Some(Side::Center)
+        }
+    }
+}
+
+/// Estimates the direction of arrival from the time difference between the left and
+/// right channel, found by generalized cross-correlation with phase transform (GCC-PHAT)
+pub struct DirectionFinder {
+    sample_rate: u32,
+    forward: Arc<dyn RealToComplex<f32>>,
+    inverse: Arc<dyn ComplexToReal<f32>>,
+    window: Vec<f32>,
+    pending: [Vec<f32>; 2],
+    /// Buffers for the transforms, reused for every frame
+    padded: Vec<f32>,
+    spectra: [Vec<Complex<f32>>; 2],
+    forward_scratch: Vec<Complex<f32>>,
+    averaged: Vec<Complex<f32>>,
+    correlation: Vec<f32>,
+    inverse_scratch: Vec<Complex<f32>>,
+    /// Sum of the whitened cross-spectra of the loud frames since the last estimate
+    cross_spectrum: Vec<Complex<f32>>,
+    bins: std::ops::Range<usize>,
+    frames: usize,
+    loud_frames: usize,
+    frames_per_estimate: usize,
+    latest: Option<Direction>,
+}
+
+impl DirectionFinder {
+    pub fn new(sample_rate: u32) -> Self {
+        let mut planner = RealFftPlanner::new();
+        let forward = planner.plan_fft_forward(FFT_SIZE);
+        let inverse = planner.plan_fft_inverse(FFT_SIZE);
+        let bin_width = sample_rate as f32 / FFT_SIZE as f32;
+        let bins = ((BAND.0 / bin_width).ceil() as usize).max(1)
+            ..((BAND.1 / bin_width).floor() as usize + 1).min(FFT_SIZE / 2);
+        Self {
+            sample_rate,
+            window: (0..FRAME_SIZE)
+                .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / FRAME_SIZE as f32).cos())
+                .collect(),
+            pending: [Vec::new(), Vec::new()],
+            padded: forward.make_input_vec(),
+            spectra: [forward.make_output_vec(), forward.make_output_vec()],
+            forward_scratch: forward.make_scratch_vec(),
+            averaged: inverse.make_input_vec(),
+            correlation: inverse.make_output_vec(),
+            inverse_scratch: inverse.make_scratch_vec(),
+            cross_spectrum: forward.make_output_vec(),
+            forward,
+            inverse,
+            bins,
+            frames: 0,
+            loud_frames: 0,
+            frames_per_estimate: ((sample_rate as f32 * AVERAGE_SECONDS) as usize / FRAME_SIZE)
+                .max(1),
+            latest: None,
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// `mic_spacing` is the distance between the two microphones in meters.
+    /// Returns the latest estimate, `None` while it's too quiet to tell.
+    pub fn process(&mut self, left: &[f32], right: &[f32], mic_spacing: f32) -> Option<Direction> {
+        self.pending[0].extend_from_slice(left);
+        self.pending[1].extend_from_slice(right);
+        while self.pending[0].len() >= FRAME_SIZE && self.pending[1].len() >= FRAME_SIZE {
+            let mut sum_squares = 0.0;
+            for (channel, spectrum) in self.pending.iter_mut().zip(&mut self.spectra) {
+                for ((padded, sample), window) in self
+                    .padded
+                    .iter_mut()
+                    .zip(channel.drain(..FRAME_SIZE))
+                    .zip(&self.window)
+                {
+                    sum_squares += sample * sample;
+                    *padded = sample * window;
+                }
+                self.padded[FRAME_SIZE..].fill(0.0);
+                self.forward
+                    .process_with_scratch(&mut self.padded, spectrum, &mut self.forward_scratch)
+                    .expect("Buffers are made by the planner");
+            }
+            self.frames += 1;
+            let level = 10.0 * (sum_squares / (2 * FRAME_SIZE) as f32).log10();
+            if level >= SILENCE_LEVEL {
+                self.loud_frames += 1;
+                for bin in self.bins.clone() {
+                    let cross = self.spectra[1][bin] * self.spectra[0][bin].conj();
+                    let magnitude = cross.norm();
+                    if magnitude > f32::EPSILON {
+                        self.cross_spectrum[bin] += cross / magnitude;
+                    }
+                }
+            }
+            if self.frames == self.frames_per_estimate {
+                self.latest = self.estimate(mic_spacing);
+                self.cross_spectrum.fill(Complex::default());
+                self.frames = 0;
+                self.loud_frames = 0;
+            }
+        }
+        self.latest
+    }
+
+    fn estimate(&mut self, mic_spacing: f32) -> Option<Direction> {
+        if self.loud_frames == 0 || mic_spacing <= 0.0 {
+            return None;
+        }
+        for (averaged, bin) in self.averaged.iter_mut().zip(&self.cross_spectrum) {
+            *averaged = bin / self.loud_frames as f32;
+        }
+        self.inverse
+            .process_with_scratch(
+                &mut self.averaged,
+                &mut self.correlation,
+                &mut self.inverse_scratch,
+            )
+            .expect("Buffers are made by the planner");
+        let correlation = &self.correlation;
+
+        // The right channel lagging behind by `lag` samples peaks at `correlation[lag]`,
+        // negative lags wrap around to the end
+        let max_lag = ((mic_spacing / SPEED_OF_SOUND * self.sample_rate as f32).ceil() as usize)
+            .min(FRAME_SIZE - 1);
+        let at = |lag: isize| correlation[lag.rem_euclid(FFT_SIZE as isize) as usize];
+        let (peak_lag, peak) = (-(max_lag as isize)..=max_lag as isize)
+            .map(|lag| (lag, at(lag)))
+            .max_by(|a, b| a.1.total_cmp(&b.1))?;
+        // Parabolic interpolation for a delay between samples
+        let (before, after) = (at(peak_lag - 1), at(peak_lag + 1));
+        let curvature = before - 2.0 * peak + after;
+        let offset = if curvature < 0.0 {
+            (0.5 * (before - after) / curvature).clamp(-0.5, 0.5)
+        } else {
+            0.0
+        };
+        let delay = (peak_lag as f32 + offset) / self.sample_rate as f32;
+
+        // Sound from the left reaches the left microphone first
+        let sine = (-delay * SPEED_OF_SOUND / mic_spacing).clamp(-1.0, 1.0);
+        Some(Direction {
+            bearing: sine.asin().to_degrees(),
+            // The inverse transform of a cross-spectrum of ones peaks at twice the bin count
+            confidence: (peak / (2 * self.bins.len()) as f32).clamp(0.0, 1.0),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 48_000;
+    /// 28 samples from one microphone to the other at 48 kHz
+    const MIC_SPACING: f32 = 0.2;
+
+    /// Noise reaching the right microphone `delay` samples after the left one,
+    /// or before for a negative `delay`
+    fn locate(delay: isize, level: f32) -> Option<Direction> {
+        let mut rng = StdRng::seed_from_u64(1);
+        let source = (0..SAMPLE_RATE)
+            .map(|_| level * rng.gen_range(-1.0..1.0))
+            .collect::<Vec<f32>>();
+        let at = |i: isize| {
+            usize::try_from(i)
+                .ok()
+                .and_then(|i| source.get(i).copied())
+                .unwrap_or(0.0)
+        };
+        let padding = 64;
+        let (left, right): (Vec<f32>, Vec<f32>) = (padding..source.len() as isize - padding)
+            .map(|i| (at(i), at(i - delay)))
+            .unzip();
+        let mut direction_finder = DirectionFinder::new(SAMPLE_RATE);
+        let mut direction = None;
+        for (left, right) in left.chunks(480).zip(right.chunks(480)) {
+            direction = direction_finder.process(left, right, MIC_SPACING);
+        }
+        direction
+    }
+
+    /// Of a source at `bearing` degrees
+    fn delay_of(bearing: f32) -> isize {
+        let delay = -bearing.to_radians().sin() * MIC_SPACING / SPEED_OF_SOUND;
+        (delay * SAMPLE_RATE as f32).round() as isize
+    }
+
+    #[test]
+    fn right_channel_lagging_is_left() {
+        let direction = locate(14, 0.3).unwrap();
+        assert!((direction.bearing + 30.0).abs() < 1.0, "{direction:?}");
+        assert!(direction.confidence > 0.5, "{direction:?}");
+        assert_eq!(direction.side(), Some(Side::Left));
+    }
+
+    #[test]
+    fn right_channel_leading_is_right() {
+        let direction = locate(-14, 0.3).unwrap();
+        assert!((direction.bearing - 30.0).abs() < 1.0, "{direction:?}");
+        assert_eq!(direction.side(), Some(Side::Right));
+    }
+
+    #[test]
+    fn bearing_follows_the_delay() {
+        for bearing in [-60.0, -45.0, 45.0, 60.0] {
+            let direction = locate(delay_of(bearing), 0.3).unwrap();
+            // Whole samples are a few degrees apart this far out
+            assert!(
+                (direction.bearing - bearing).abs() < 3.0,
+                "{bearing}: {direction:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn zero_delay_is_center() {
+        let direction = locate(0, 0.3).unwrap();
+        assert!(direction.bearing.abs() < 1.0, "{direction:?}");
+        assert_eq!(direction.side(), Some(Side::Center));
+    }
+
+    #[test]
+    fn silence_has_no_direction() {
+        assert_eq!(locate(14, 0.0), None);
+    }
+
+    #[test]
+    fn unrelated_channels_have_no_side() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let mut noise = || {
+            (0..SAMPLE_RATE)
+                .map(|_| rng.gen_range(-0.3..0.3))
+                .collect::<Vec<f32>>()
+        };
+        let (left, right) = (noise(), noise());
+        let mut direction_finder = DirectionFinder::new(SAMPLE_RATE);
+        let mut direction = None;
+        for (left, right) in left.chunks(480).zip(right.chunks(480)) {
+            direction = direction_finder.process(left, right, MIC_SPACING);
+        }
+        let direction = direction.unwrap();
+        assert_eq!(direction.side(), None, "{direction:?}");
+    }
+}
@@ -0,0 +1,679 @@
+use std::{
+    collections::VecDeque,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use realfft::{num_complex::Complex, ComplexToReal, RealFftPlanner, RealToComplex};
+
+/// Length of the echo path that can be modelled, about 43 ms at 48 kHz
+const TAPS: usize = 2048;
+/// Taps per partition of the filter, and how many samples it's updated with at once
+const PARTITION_LEN: usize = 256;
+const PARTITIONS: usize = TAPS / PARTITION_LEN;
+/// Where the echo is put in the filter once its delay is known, so an echo that
+/// comes a little earlier still fits
+const MARGIN: usize = TAPS / 8;
+/// NLMS step size. Small, so the crowd talking over the music
+/// doesn't throw the filter off too far, at the cost of slower convergence.
+const STEP: f32 = 0.05;
+/// Keeps the step from blowing up while the reference is silent
+const REGULARIZATION: f32 = 1e-6;
+/// How much later the echo may reach the microphone than the reference arrives,
+/// e.g. because the two devices buffer differently
+const MAX_DELAY: Duration = Duration::from_secs(1);
+/// How much earlier the echo may reach the microphone than the reference arrives.
+/// Microphone samples wait this long at most for their reference.
+const MAX_LEAD: Duration = Duration::from_millis(250);
+/// Microphone signal correlated with the reference to find the delay
+const ESTIMATE_WINDOW: Duration = Duration::from_millis(500);
+/// The delay is estimated again this often, following clock drift between the devices
+const ESTIMATE_INTERVAL: Duration = Duration::from_secs(1);
+/// The delay is estimated on sums of this many samples, close enough to put the echo
+/// well within [`MARGIN`] for a fraction of the work
+const DECIMATION: usize = 4;
+/// How far the correlation peak has to stand out from the other lags to be trusted
+const MIN_PEAK_RATIO: f32 = 8.0;
+/// Without reference samples for this long, the output monitor is considered gone
+const REFERENCE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Removes what the speakers play, as captured from the output monitor,
+/// from the microphone signal with a normalized least mean squares filter.
+/// What's left is the crowd.
+///
+/// The two devices start at different times and their clocks drift apart, so how far
+/// the echo lags behind the reference is estimated by cross-correlation every
+/// [`ESTIMATE_INTERVAL`], and the reference is lined up to put the echo inside the filter.
+pub struct EchoCanceller {
+    /// Of the microphone, the reference is resampled to it
+    sample_rate: u32,
+    resampler: Resampler,
+    filter: PartitionedFilter,
+    /// The reference of the previous block and the one being processed
+    frame: Vec<f32>,
+    /// The microphone samples being processed
+    block: Vec<f32>,
+    /// The latest reference samples, the first being the `reference_start`th ever pushed
+    references: VecDeque<f32>,
+    reference_start: i64,
+    max_references: usize,
+    /// Microphone samples waiting for their reference
+    waiting: VecDeque<f32>,
+    max_waiting: usize,
+    /// Counts every microphone sample so far
+    mic_received: i64,
+    /// The latest microphone samples, for estimating the delay
+    mic_history: VecDeque<f32>,
+    /// Microphone sample `n` is paired with reference sample `n + offset`,
+    /// `None` until the first microphone block lines up the latest samples
+    offset: Option<i64>,
+    until_estimate: usize,
+    estimate_interval: usize,
+    delay: DelayEstimator,
+    last_reference_at: Instant,
+}
+
+impl EchoCanceller {
+    pub fn new(sample_rate: u32) -> Self {
+        let samples = |duration: Duration| (sample_rate as f32 * duration.as_secs_f32()) as usize;
+        let delay = DelayEstimator::new(
+            samples(ESTIMATE_WINDOW),
+            samples(MAX_LEAD),
+            samples(MAX_DELAY),
+        );
+        Self {
+            sample_rate,
+            resampler: Resampler::new(sample_rate),
+            filter: PartitionedFilter::new(),
+            frame: vec![0.0; 2 * PARTITION_LEN],
+            block: Vec::with_capacity(PARTITION_LEN),
+            references: VecDeque::new(),
+            reference_start: 0,
+            max_references: delay.reference_len() + TAPS + PARTITION_LEN,
+            waiting: VecDeque::new(),
+            max_waiting: samples(MAX_LEAD),
+            mic_received: 0,
+            mic_history: VecDeque::new(),
+            offset: None,
+            until_estimate: samples(ESTIMATE_INTERVAL),
+            estimate_interval: samples(ESTIMATE_INTERVAL),
+            delay,
+            last_reference_at: Instant::now(),
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// `sample_rate` is the output monitor's, which may differ from the microphone's
+    pub fn push_reference(&mut self, samples: &[f32], sample_rate: u32) {
+        self.last_reference_at = Instant::now();
+        self.resampler
+            .process(samples, sample_rate, &mut self.references);
+        let excess = self.references.len().saturating_sub(self.max_references);
+        self.references.drain(..excess);
+        self.reference_start += excess as i64;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.last_reference_at.elapsed() < REFERENCE_TIMEOUT
+    }
+
+    /// Replaces `residual` with the microphone signal without the echo. Samples whose
+    /// reference hasn't arrived yet, or that don't fill a partition yet, come with a later
+    /// block, so `residual` may be shorter.
+    pub fn process(&mut self, mic: &[f32], residual: &mut Vec<f32>) {
+        self.waiting.extend(mic);
+        self.mic_received += mic.len() as i64;
+        self.mic_history.extend(mic);
+        let excess = self.mic_history.len().saturating_sub(self.delay.mic_len());
+        self.mic_history.drain(..excess);
+
+        let reference_end = self.reference_end();
+        let mic_received = self.mic_received;
+        let mut offset = *self.offset.get_or_insert(reference_end - mic_received);
+        self.until_estimate = self.until_estimate.saturating_sub(mic.len());
+        if self.until_estimate == 0 {
+            self.until_estimate = self.estimate_interval;
+            if let Some(echo_offset) = self.estimate_echo_offset() {
+                // Drift within the filter is followed by the filter itself
+                let tap = offset - echo_offset;
+                if !(MARGIN as i64 / 2..(TAPS - MARGIN) as i64).contains(&tap) {
+                    offset = echo_offset + MARGIN as i64;
+                    self.realign(offset);
+                }
+            }
+        }
+
+        let processed = self.mic_received - self.waiting.len() as i64;
+        let arrived = (reference_end - offset - processed).max(0) as usize;
+        let late = self.waiting.len().saturating_sub(self.max_waiting);
+        let ready = arrived.max(late).min(self.waiting.len());
+
+        residual.clear();
+        for start in (processed..)
+            .step_by(PARTITION_LEN)
+            .take(ready / PARTITION_LEN)
+        {
+            self.fill_frame(start + offset);
+            self.filter.push_frame(&self.frame);
+            self.block.clear();
+            self.block.extend(self.waiting.drain(..PARTITION_LEN));
+            self.filter.process(&self.block, residual);
+        }
+    }
+
+    /// With the reference of the partition ending just before `end` and the one after
+    fn fill_frame(&mut self, end: i64) {
+        let start = end - PARTITION_LEN as i64;
+        for (index, sample) in (start..).zip(&mut self.frame) {
+            *sample = reference_at(&self.references, self.reference_start, index);
+        }
+    }
+
+    fn reference_end(&self) -> i64 {
+        self.reference_start + self.references.len() as i64
+    }
+
+    /// The offset that pairs the microphone with the reference sample it hears the echo of
+    fn estimate_echo_offset(&mut self) -> Option<i64> {
+        if self.mic_history.len() < self.delay.mic_len()
+            || self.references.len() < self.delay.reference_len()
+        {
+            return None;
+        }
+        let references = self
+            .references
+            .range(self.references.len() - self.delay.reference_len()..);
+        let lag = self.delay.estimate(&self.mic_history, references)?;
+        Some(self.reference_end() - self.mic_received - self.delay.max_delay() as i64 + lag as i64)
+    }
+
+    /// Pairs the microphone with other reference samples from now on, keeping what the
+    /// filter learned about the echo path where it still fits
+    fn realign(&mut self, offset: i64) {
+        let previous = self.offset.replace(offset).unwrap_or(offset);
+        self.filter.shift(offset - previous);
+
+        // The partitions processed last, the oldest first
+        let processed = self.mic_received - self.waiting.len() as i64;
+        for partition in (1..=PARTITIONS as i64).rev() {
+            self.fill_frame(processed - partition * PARTITION_LEN as i64 + offset);
+            self.filter.push_frame(&self.frame);
+        }
+    }
+}
+
+/// Normalized least mean squares in the frequency domain. The taps are split into
+/// [`PARTITIONS`], each applied to its own stretch of the reference by multiplying spectra,
+/// so the work per sample grows with the log of [`PARTITION_LEN`] instead of with [`TAPS`].
+struct PartitionedFilter {
+    forward: Arc<dyn RealToComplex<f32>>,
+    inverse: Arc<dyn ComplexToReal<f32>>,
+    /// By partition, the taps padded with as many zeros, transformed
+    weights: Vec<Vec<Complex<f32>>>,
+    /// By partition, the reference it's applied to: the partition before and its own,
+    /// transformed, the latest first
+    frames: VecDeque<Vec<Complex<f32>>>,
+    /// Of the reference in each partition, the latest first
+    energies: VecDeque<f32>,
+    time: Vec<f32>,
+    spectrum: Vec<Complex<f32>>,
+    error: Vec<Complex<f32>>,
+    forward_scratch: Vec<Complex<f32>>,
+    inverse_scratch: Vec<Complex<f32>>,
+}
+
+impl PartitionedFilter {
+    fn new() -> Self {
+        let mut planner = RealFftPlanner::new();
+        let forward = planner.plan_fft_forward(2 * PARTITION_LEN);
+        let inverse = planner.plan_fft_inverse(2 * PARTITION_LEN);
+        Self {
+            weights: (0..PARTITIONS).map(|_| forward.make_output_vec()).collect(),
+            frames: (0..PARTITIONS).map(|_| forward.make_output_vec()).collect(),
+            energies: VecDeque::from(vec![0.0; PARTITIONS]),
+            time: forward.make_input_vec(),
+            spectrum: forward.make_output_vec(),
+            error: forward.make_output_vec(),
+            forward_scratch: forward.make_scratch_vec(),
+            inverse_scratch: inverse.make_scratch_vec(),
+            forward,
+            inverse,
+        }
+    }
+
+    /// Takes the reference of the previous partition followed by the one that's processed next
+    fn push_frame(&mut self, frame: &[f32]) {
+        let mut spectrum = self.frames.pop_back().expect("One frame per partition");
+        self.time.copy_from_slice(frame);
+        self.forward
+            .process_with_scratch(&mut self.time, &mut spectrum, &mut self.forward_scratch)
+            .expect("Buffers are made by the planner");
+        self.frames.push_front(spectrum);
+        self.energies.pop_back();
+        self.energies
+            .push_front(frame[PARTITION_LEN..].iter().map(|x| x * x).sum());
+    }
+
+    /// Appends `mic`, one partition long, without the echo to `residual`
+    fn process(&mut self, mic: &[f32], residual: &mut Vec<f32>) {
+        let scale = 1.0 / (2 * PARTITION_LEN) as f32;
+        self.spectrum.fill(Complex::default());
+        for (weights, frame) in self.weights.iter().zip(&self.frames) {
+            for ((echo, w), x) in self.spectrum.iter_mut().zip(weights).zip(frame) {
+                *echo += w * x;
+            }
+        }
+        Self::inverse(
+            &self.inverse,
+            &mut self.spectrum,
+            &mut self.time,
+            &mut self.inverse_scratch,
+        );
+        // Overlap-save: the second half doesn't wrap around
+        let start = residual.len();
+        residual.extend(
+            mic.iter()
+                .zip(&self.time[PARTITION_LEN..])
+                .map(|(sample, echo)| sample - echo * scale),
+        );
+
+        // Summed once per partition instead of updated per sample,
+        // so rounding errors don't add up
+        let step = STEP / (self.energies.iter().sum::<f32>() + REGULARIZATION);
+        self.time[..PARTITION_LEN].fill(0.0);
+        self.time[PARTITION_LEN..].copy_from_slice(&residual[start..]);
+        self.forward
+            .process_with_scratch(&mut self.time, &mut self.error, &mut self.forward_scratch)
+            .expect("Buffers are made by the planner");
+        for (weights, frame) in self.weights.iter_mut().zip(&self.frames) {
+            // Correlates the error with the reference, the first half being the partition's taps
+            for ((gradient, x), error) in self.spectrum.iter_mut().zip(frame).zip(&self.error) {
+                *gradient = x.conj() * error;
+            }
+            Self::inverse(
+                &self.inverse,
+                &mut self.spectrum,
+                &mut self.time,
+                &mut self.inverse_scratch,
+            );
+            for tap in &mut self.time[..PARTITION_LEN] {
+                *tap *= step * scale;
+            }
+            // The rest would wrap around into the other taps
+            self.time[PARTITION_LEN..].fill(0.0);
+            self.forward
+                .process_with_scratch(
+                    &mut self.time,
+                    &mut self.spectrum,
+                    &mut self.forward_scratch,
+                )
+                .expect("Buffers are made by the planner");
+            for (w, gradient) in weights.iter_mut().zip(&self.spectrum) {
+                *w += gradient;
+            }
+        }
+    }
+
+    /// Moves every tap `shift` later, dropping those that no longer fit
+    fn shift(&mut self, shift: i64) {
+        let scale = 1.0 / (2 * PARTITION_LEN) as f32;
+        let mut taps = vec![0.0; TAPS];
+        for (weights, taps) in self.weights.iter().zip(taps.chunks_mut(PARTITION_LEN)) {
+            self.spectrum.copy_from_slice(weights);
+            Self::inverse(
+                &self.inverse,
+                &mut self.spectrum,
+                &mut self.time,
+                &mut self.inverse_scratch,
+            );
+            for (tap, &x) in taps.iter_mut().zip(&self.time) {
+                *tap = x * scale;
+            }
+        }
+        if shift.unsigned_abs() as usize >= TAPS {
+            taps.fill(0.0);
+        } else if shift > 0 {
+            let shift = shift as usize;
+            taps.copy_within(..TAPS - shift, shift);
+            taps[..shift].fill(0.0);
+        } else {
+            let shift = shift.unsigned_abs() as usize;
+            taps.copy_within(shift.., 0);
+            taps[TAPS - shift..].fill(0.0);
+        }
+        for (weights, taps) in self.weights.iter_mut().zip(taps.chunks(PARTITION_LEN)) {
+            self.time[..PARTITION_LEN].copy_from_slice(taps);
+            self.time[PARTITION_LEN..].fill(0.0);
+            self.forward
+                .process_with_scratch(&mut self.time, weights, &mut self.forward_scratch)
+                .expect("Buffers are made by the planner");
+        }
+    }
+
+    /// Transforms `spectrum` into `time`, unscaled
+    fn inverse(
+        inverse: &Arc<dyn ComplexToReal<f32>>,
+        spectrum: &mut [Complex<f32>],
+        time: &mut [f32],
+        scratch: &mut [Complex<f32>],
+    ) {
+        // Real for real signals, rounding errors aside, and the transform length is even
+        spectrum[0].im = 0.0;
+        if let Some(last) = spectrum.last_mut() {
+            last.im = 0.0;
+        }
+        inverse
+            .process_with_scratch(spectrum, time, scratch)
+            .expect("Buffers are made by the planner");
+    }
+}
+
+/// Turns the output monitor's samples into the microphone's sample rate by linear
+/// interpolation. Rough, but the filter adapts to what's left of the echo path.
+struct Resampler {
+    to: u32,
+    /// Of the latest input, interpolation starts over when it changes
+    from: u32,
+    /// Of the next output sample, in input samples after `previous`
+    position: f64,
+    previous: f32,
+}
+
+impl Resampler {
+    fn new(to: u32) -> Self {
+        Self {
+            to,
+            from: to,
+            position: 0.0,
+            previous: 0.0,
+        }
+    }
+
+    fn process(&mut self, samples: &[f32], from: u32, output: &mut VecDeque<f32>) {
+        if from != self.from {
+            self.from = from;
+            self.position = 0.0;
+        }
+        if from == self.to {
+            output.extend(samples);
+            self.previous = samples.last().copied().unwrap_or(self.previous);
+            return;
+        }
+        let step = f64::from(from) / f64::from(self.to);
+        let previous = self.previous;
+        let at = |index: usize| {
+            index
+                .checked_sub(1)
+                .map_or(previous, |index| samples[index])
+        };
+        while self.position < samples.len() as f64 {
+            let index = self.position as usize;
+            let fraction = (self.position - index as f64) as f32;
+            output.push_back(at(index) + (at(index + 1) - at(index)) * fraction);
+            self.position += step;
+        }
+        self.position -= samples.len() as f64;
+        self.previous = samples.last().copied().unwrap_or(self.previous);
+    }
+}
+
+/// Silence for samples that haven't arrived or were dropped
+fn reference_at(references: &VecDeque<f32>, start: i64, index: i64) -> f32 {
+    usize::try_from(index - start)
+        .ok()
+        .and_then(|i| references.get(i).copied())
+        .unwrap_or(0.0)
+}
+
+/// Finds how far the microphone lags behind the reference by generalized
+/// cross-correlation with phase transform, like [`super::DirectionFinder`].
+/// Lengths are counted in sums of [`DECIMATION`] samples.
+struct DelayEstimator {
+    /// Microphone signal correlated, ending `max_lead` before the latest
+    window: usize,
+    max_lead: usize,
+    max_delay: usize,
+    forward: Arc<dyn RealToComplex<f32>>,
+    inverse: Arc<dyn ComplexToReal<f32>>,
+    /// Zero padded to the length of the reference
+    mic: Vec<f32>,
+    reference: Vec<f32>,
+    mic_spectrum: Vec<Complex<f32>>,
+    reference_spectrum: Vec<Complex<f32>>,
+    correlation: Vec<f32>,
+    forward_scratch: Vec<Complex<f32>>,
+    inverse_scratch: Vec<Complex<f32>>,
+}
+
+impl DelayEstimator {
+    /// Takes lengths in samples
+    fn new(window: usize, max_lead: usize, max_delay: usize) -> Self {
+        let (window, max_lead, max_delay) = (
+            window / DECIMATION,
+            max_lead / DECIMATION,
+            max_delay / DECIMATION,
+        );
+        // Long enough that no lag searched wraps around, and even so the last bin is real
+        let fft_size = (window + max_lead + max_delay).next_power_of_two();
+        let mut planner = RealFftPlanner::new();
+        let forward = planner.plan_fft_forward(fft_size);
+        let inverse = planner.plan_fft_inverse(fft_size);
+        Self {
+            window,
+            max_lead,
+            max_delay,
+            mic: forward.make_input_vec(),
+            reference: forward.make_input_vec(),
+            mic_spectrum: forward.make_output_vec(),
+            reference_spectrum: forward.make_output_vec(),
+            correlation: inverse.make_output_vec(),
+            forward_scratch: forward.make_scratch_vec(),
+            inverse_scratch: inverse.make_scratch_vec(),
+            forward,
+            inverse,
+        }
+    }
+
+    /// In samples
+    fn mic_len(&self) -> usize {
+        (self.window + self.max_lead) * DECIMATION
+    }
+
+    /// In samples
+    fn reference_len(&self) -> usize {
+        (self.window + self.max_lead + self.max_delay) * DECIMATION
+    }
+
+    /// In samples
+    fn max_delay(&self) -> usize {
+        self.max_delay * DECIMATION
+    }
+
+    /// Takes the latest [`Self::mic_len`] microphone and [`Self::reference_len`] reference
+    /// samples. Returns the lag in samples where the correlation peaks, from 0 for the echo
+    /// being `max_delay` behind the latest samples lined up to `max_delay + max_lead` for
+    /// `max_lead` ahead, `None` when there's no clear peak, e.g. while the music is off.
+    fn estimate<'a>(
+        &mut self,
+        mic: &VecDeque<f32>,
+        references: impl Iterator<Item = &'a f32>,
+    ) -> Option<usize> {
+        self.mic.fill(0.0);
+        for (index, &sample) in mic.range(..self.window * DECIMATION).enumerate() {
+            self.mic[index / DECIMATION] += sample;
+        }
+        self.reference.fill(0.0);
+        for (index, &sample) in references.enumerate() {
+            self.reference[index / DECIMATION] += sample;
+        }
+        let silent = |samples: &[f32]| samples.iter().all(|&x| x.abs() < f32::EPSILON);
+        if silent(&self.mic) || silent(&self.reference) {
+            return None;
+        }
+        self.forward
+            .process_with_scratch(
+                &mut self.mic,
+                &mut self.mic_spectrum,
+                &mut self.forward_scratch,
+            )
+            .expect("Buffers are made by the planner");
+        self.forward
+            .process_with_scratch(
+                &mut self.reference,
+                &mut self.reference_spectrum,
+                &mut self.forward_scratch,
+            )
+            .expect("Buffers are made by the planner");
+
+        // `correlation[lag]` sums the microphone times the reference `lag` samples further on
+        for (reference, mic) in self.reference_spectrum.iter_mut().zip(&self.mic_spectrum) {
+            let cross = *reference * mic.conj();
+            let magnitude = cross.norm();
+            *reference = if magnitude > f32::EPSILON {
+                cross / magnitude
+            } else {
+                Complex::default()
+            };
+        }
+        // Real for real signals, rounding errors aside, and the transform length is even
+        self.reference_spectrum[0].im = 0.0;
+        if let Some(last) = self.reference_spectrum.last_mut() {
+            last.im = 0.0;
+        }
+        self.inverse
+            .process_with_scratch(
+                &mut self.reference_spectrum,
+                &mut self.correlation,
+                &mut self.inverse_scratch,
+            )
+            .expect("Buffers are made by the planner");
+
+        let lags = &self.correlation[..=self.max_delay + self.max_lead];
+        let (lag, &peak) = lags.iter().enumerate().max_by(|a, b| a.1.total_cmp(b.1))?;
+        let rms = (lags.iter().map(|c| c * c).sum::<f32>() / lags.len() as f32).sqrt();
+        (peak > MIN_PEAK_RATIO * rms).then_some(lag * DECIMATION)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+
+    /// Lower than the devices', the filter converges within as many samples at any rate
+    const SAMPLE_RATE: u32 = 16_000;
+    const BLOCK: usize = 160;
+
+    /// Plays noise as the music, which reaches the microphone `delay` samples after the
+    /// output monitor captures it, or before for a negative `delay`. Returns the level of
+    /// the microphone and of what's left of it over the last seconds, in dB.
+    fn cancel(delay: isize, crowd: f32) -> (f32, f32) {
+        let mut rng = StdRng::seed_from_u64(1);
+        let seconds = 10;
+        let music = (0..seconds * SAMPLE_RATE as usize)
+            .map(|_| rng.gen_range(-0.3..0.3))
+            .collect::<Vec<f32>>();
+        let lead = delay.min(0).unsigned_abs();
+        let delay = delay.max(0).unsigned_abs();
+        let blocks = (0..music.len() - lead).step_by(BLOCK).map(|start| {
+            let mic = (start..start + BLOCK)
+                .map(|i| {
+                    let echo = (i + lead).checked_sub(delay).map_or(0.0, |i| music[i]);
+                    0.5 * echo + crowd * rng.gen_range(-1.0..1.0)
+                })
+                .collect::<Vec<f32>>();
+            (music[start..start + BLOCK].to_vec(), mic)
+        });
+        levels(SAMPLE_RATE, blocks.collect())
+    }
+
+    /// Feeds pairs of reference and microphone blocks. Returns the level of the microphone
+    /// and of what's left of it over the last two seconds, in dB.
+    fn levels(reference_rate: u32, blocks: Vec<(Vec<f32>, Vec<f32>)>) -> (f32, f32) {
+        let mut echo_canceller = EchoCanceller::new(SAMPLE_RATE);
+        let mut residual = Vec::new();
+        let (mut mic_energy, mut residual_energy) = (0.0, 0.0);
+        let measured_from = blocks.len() - 2 * SAMPLE_RATE as usize / BLOCK;
+        for (index, (reference, mic)) in blocks.iter().enumerate() {
+            echo_canceller.push_reference(reference, reference_rate);
+            echo_canceller.process(mic, &mut residual);
+            if index >= measured_from {
+                mic_energy += mic.iter().map(|x| x * x).sum::<f32>();
+                residual_energy += residual.iter().map(|x| x * x).sum::<f32>();
+            }
+        }
+        (10.0 * mic_energy.log10(), 10.0 * residual_energy.log10())
+    }
+
+    #[test]
+    fn cancels_an_echo_longer_than_the_filter() {
+        let (mic, residual) = cancel(SAMPLE_RATE as isize * 3 / 10, 0.0);
+        assert!(mic - residual > 20.0, "{mic} dB to {residual} dB");
+    }
+
+    #[test]
+    fn cancels_an_echo_without_delay() {
+        let (mic, residual) = cancel(0, 0.0);
+        assert!(mic - residual > 20.0, "{mic} dB to {residual} dB");
+    }
+
+    #[test]
+    fn cancels_an_echo_ahead_of_the_reference() {
+        let (mic, residual) = cancel(-(SAMPLE_RATE as isize) / 10, 0.0);
+        assert!(mic - residual > 20.0, "{mic} dB to {residual} dB");
+    }
+
+    #[test]
+    fn keeps_the_crowd() {
+        let (mic, residual) = cancel(SAMPLE_RATE as isize * 3 / 10, 0.05);
+        // Uniform noise has a third of its peak squared as power, the crowd is 10 dB below the echo
+        let (echo_power, crowd_power) = ((0.5f32 * 0.3).powi(2) / 3.0, 0.05f32.powi(2) / 3.0);
+        let crowd = mic - 10.0 * (1.0 + echo_power / crowd_power).log10();
+        assert!(
+            (residual - crowd).abs() < 1.0,
+            "{residual} dB, crowd {crowd} dB"
+        );
+    }
+
+    #[test]
+    fn cancels_an_echo_from_a_monitor_at_another_sample_rate() {
+        // Like 44.1 kHz against 48 kHz
+        let reference_rate = SAMPLE_RATE * 147 / 160;
+        let mut rng = StdRng::seed_from_u64(1);
+        // Noise, but known at any point in time by interpolating between random points
+        let points_per_second = 4000.0;
+        let points = (0..11 * points_per_second as usize)
+            .map(|_| rng.gen_range(-0.3..0.3))
+            .collect::<Vec<f32>>();
+        let music = |time: f64| {
+            let position = time.max(0.0) * points_per_second;
+            let index = position as usize;
+            let fraction = (position - index as f64) as f32;
+            points[index] + (points[index + 1] - points[index]) * fraction
+        };
+        let at_rate = |rate: u32, samples: std::ops::Range<usize>, delay: f64| {
+            samples
+                .map(|i| music(i as f64 / f64::from(rate) - delay))
+                .collect::<Vec<f32>>()
+        };
+        let reference_block = reference_rate as usize / 100;
+        let blocks = (0..1000)
+            .map(|block| {
+                let reference = at_rate(
+                    reference_rate,
+                    block * reference_block..(block + 1) * reference_block,
+                    0.0,
+                );
+                let mic = at_rate(SAMPLE_RATE, block * BLOCK..(block + 1) * BLOCK, 0.3);
+                (reference, mic.iter().map(|x| 0.5 * x).collect())
+            })
+            .collect();
+        let (mic, residual) = levels(reference_rate, blocks);
+        // Music that isn't white converges slower, and interpolation is a little off
+        assert!(mic - residual > 15.0, "{mic} dB to {residual} dB");
+    }
+}
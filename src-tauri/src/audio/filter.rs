@@ -0,0 +1,125 @@
+use std::f32::consts::PI;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum FilterMode {
+    /// Removes frequencies above the cutoff frequency.
+    LowPass,
+    /// Removes frequencies above and below the cutoff frequency.
+    BandPass,
+    /// Removes frequencies below the cutoff frequency.
+    HighPass,
+    /// Removes frequencies around the cutoff frequency.
+    Notch,
+}
+
+/// One stage of a filter chain, as configured
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct FilterStage {
+    pub mode: FilterMode,
+    /// In Hz
+    pub cutoff: f32,
+    /// From 0 to 1, higher is a sharper peak or narrower notch
+    pub resonance: f32,
+}
+
+/// A state-variable filter
+pub struct Filter {
+    stage: FilterStage,
+    sample_rate: f32,
+    k: f32,
+    a1: f32,
+    a2: f32,
+    a3: f32,
+    ic1eq: f32,
+    ic2eq: f32,
+}
+
+impl Filter {
+    pub fn new(stage: FilterStage, sample_rate: f32) -> Self {
+        let mut filter = Self {
+            stage,
+            sample_rate,
+            k: 0.0,
+            a1: 0.0,
+            a2: 0.0,
+            a3: 0.0,
+            ic1eq: 0.0,
+            ic2eq: 0.0,
+        };
+        filter.update_coefficients();
+        filter
+    }
+
+    /// Keeps the filter state, so changing the cutoff while running doesn't click
+    pub fn set(&mut self, stage: FilterStage) {
+        if stage != self.stage {
+            self.stage = stage;
+            self.update_coefficients();
+        }
+    }
+
+    fn update_coefficients(&mut self) {
+        // above nyquist the tangent wraps around
+        let cutoff = self.stage.cutoff.clamp(1.0, 0.49 * self.sample_rate);
+        let g = (PI * (cutoff / self.sample_rate)).tan();
+        self.k = 2.0 - (1.9 * self.stage.resonance.clamp(0.0, 1.0));
+        self.a1 = 1.0 / (1.0 + (g * (g + self.k)));
+        self.a2 = g * self.a1;
+        self.a3 = g * self.a2;
+    }
+
+    // copied from https://github.com/tesselode/kira/blob/main/crates/kira/src/effect/filter.rs
+    pub fn process(&mut self, sample: f32) -> f32 {
+        let v3 = sample - self.ic2eq;
+        let v1 = (self.ic1eq * self.a1) + (v3 * self.a2);
+        let v2 = self.ic2eq + (self.ic1eq * self.a2) + (v3 * self.a3);
+        self.ic1eq = (v1 * 2.0) - self.ic1eq;
+        self.ic2eq = (v2 * 2.0) - self.ic2eq;
+        match self.stage.mode {
+            FilterMode::LowPass => v2,
+            FilterMode::BandPass => v1,
+            FilterMode::HighPass => sample - v1 * self.k - v2,
+            FilterMode::Notch => sample - v1 * self.k,
+        }
+    }
+}
+
+/// [`Filter`]s applied one after the other, e.g. a notch for mains hum and a low-pass
+#[derive(Default)]
+pub struct FilterChain {
+    filters: Vec<Filter>,
+    /// Reused between blocks
+    filtered: Vec<f32>,
+}
+
+impl FilterChain {
+    /// Only stages that changed get new coefficients, the others keep running undisturbed
+    pub fn update(&mut self, stages: &[FilterStage], sample_rate: f32) {
+        self.filters.truncate(stages.len());
+        for (filter, &stage) in self.filters.iter_mut().zip(stages) {
+            filter.set(stage);
+        }
+        let existing = self.filters.len();
+        self.filters.extend(
+            stages[existing..]
+                .iter()
+                .map(|&stage| Filter::new(stage, sample_rate)),
+        );
+    }
+
+    /// Returns `samples` as they are when there are no stages
+    pub fn process<'a>(&'a mut self, samples: &'a [f32]) -> &'a [f32] {
+        if self.filters.is_empty() {
+            return samples;
+        }
+        self.filtered.clear();
+        self.filtered.extend(samples.iter().map(|&sample| {
+            self.filters
+                .iter_mut()
+                .fold(sample, |sample, filter| filter.process(sample))
+        }));
+        &self.filtered
+    }
+}
@@ -0,0 +1,142 @@
+use serde::Serialize;
+
+/// Samples at least this far from zero count as clipped
+const CLIP_LEVEL: f32 = 0.999;
+/// Share of clipped samples above which the preamp is too hot
+const MAX_CLIPPING_RATIO: f32 = 0.001;
+/// Samples closer to zero than this are digital silence, not just a quiet room
+const SILENT_LEVEL: f32 = 1e-6;
+/// Digital silence for this long means the input is dead, e.g. muted or unplugged
+const DEAD_SECONDS: f32 = 10.0;
+/// Mean sample value beyond which the input has a DC offset
+const MAX_DC_OFFSET: f32 = 0.05;
+/// Clipping, DC offset and invalid samples are judged over windows this long
+const WINDOW_SECONDS: f32 = 5.0;
+
+/// What's wrong with the microphone's signal
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Problem {
+    Clipping,
+    /// Nothing but digital silence
+    DeadInput,
+    DcOffset,
+    /// NaN or infinite samples, blocks with them aren't measured
+    InvalidSamples,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct MicHealth {
+    /// Share of the samples of the last window that were clipped
+    pub clipping_ratio: f32,
+    /// How long there has been nothing but digital silence, in whole seconds
+    pub silent_seconds: f32,
+    /// Mean sample value over the last window
+    pub dc_offset: f32,
+    /// NaN or infinite samples in the last window
+    pub invalid_samples: usize,
+    pub problems: Vec<Problem>,
+}
+
+impl MicHealth {
+    pub fn is_healthy(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// Raw samples of one block across all channels, gathered while they're read anyway
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SampleStats {
+    pub samples: usize,
+    pub clipped: usize,
+    pub invalid: usize,
+    pub sum: f64,
+    /// Every sample is digital silence
+    pub silent: bool,
+}
+
+impl SampleStats {
+    pub fn of<'a>(samples: impl IntoIterator<Item = &'a f32>) -> Self {
+        let mut stats = Self {
+            silent: true,
+            ..Self::default()
+        };
+        for &sample in samples {
+            stats.samples += 1;
+            if !sample.is_finite() {
+                stats.invalid += 1;
+                continue;
+            }
+            stats.sum += f64::from(sample);
+            if sample.abs() >= CLIP_LEVEL {
+                stats.clipped += 1;
+            }
+            if sample.abs() >= SILENT_LEVEL {
+                stats.silent = false;
+            }
+        }
+        stats
+    }
+}
+
+/// Judges the health of a microphone from the [`SampleStats`] of its blocks
+pub(super) struct HealthMonitor {
+    sample_rate: u32,
+    window: SampleStats,
+    window_frames: usize,
+    silent_frames: usize,
+    health: MicHealth,
+}
+
+impl HealthMonitor {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            window: SampleStats::default(),
+            window_frames: 0,
+            silent_frames: 0,
+            health: MicHealth::default(),
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn push(&mut self, stats: &SampleStats, frames: usize) -> &MicHealth {
+        if stats.silent {
+            self.silent_frames += frames;
+        } else {
+            self.silent_frames = 0;
+        }
+        self.window.samples += stats.samples;
+        self.window.clipped += stats.clipped;
+        self.window.invalid += stats.invalid;
+        self.window.sum += stats.sum;
+        self.window_frames += frames;
+
+        self.health.silent_seconds = (self.silent_frames as f32 / self.sample_rate as f32).floor();
+        if self.window_frames as f32 >= WINDOW_SECONDS * self.sample_rate as f32 {
+            let valid = self.window.samples - self.window.invalid;
+            self.health.clipping_ratio = self.window.clipped as f32 / valid.max(1) as f32;
+            self.health.dc_offset = (self.window.sum / valid.max(1) as f64) as f32;
+            self.health.invalid_samples = self.window.invalid;
+            self.window = SampleStats::default();
+            self.window_frames = 0;
+        }
+
+        let health = &mut self.health;
+        health.problems = [
+            (
+                health.clipping_ratio > MAX_CLIPPING_RATIO,
+                Problem::Clipping,
+            ),
+            (health.silent_seconds >= DEAD_SECONDS, Problem::DeadInput),
+            (health.dc_offset.abs() > MAX_DC_OFFSET, Problem::DcOffset),
+            (health.invalid_samples > 0, Problem::InvalidSamples),
+        ]
+        .into_iter()
+        .filter_map(|(present, problem)| present.then_some(problem))
+        .collect();
+        &self.health
+    }
+}
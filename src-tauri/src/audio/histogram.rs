@@ -0,0 +1,65 @@
+/// Levels below this many dB are counted as this, including silence
+const MIN_LEVEL: f32 = -150.0;
+/// High enough for dB SPL as well as dBFS
+const MAX_LEVEL: f32 = 150.0;
+/// Resolution in dB
+const BIN_WIDTH: f32 = 0.1;
+const BIN_COUNT: usize = ((MAX_LEVEL - MIN_LEVEL) / BIN_WIDTH) as usize;
+
+/// Counts levels in narrow bins, so percentiles of a sliding window don't need sorting
+pub struct LevelHistogram {
+    counts: Vec<u32>,
+    total: u32,
+}
+
+impl Default for LevelHistogram {
+    fn default() -> Self {
+        Self {
+            counts: vec![0; BIN_COUNT],
+            total: 0,
+        }
+    }
+}
+
+impl LevelHistogram {
+    pub fn add(&mut self, level: f32) {
+        self.counts[bin(level)] += 1;
+        self.total += 1;
+    }
+
+    /// `level` has to have been added before
+    pub fn remove(&mut self, level: f32) {
+        let count = &mut self.counts[bin(level)];
+        *count = count.saturating_sub(1);
+        self.total = self.total.saturating_sub(1);
+    }
+
+    pub fn len(&self) -> usize {
+        self.total as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.total == 0
+    }
+
+    /// The level that `share` of the levels are at or below, e.g. 0.5 for the median
+    pub fn percentile(&self, share: f32) -> Option<f32> {
+        if self.total == 0 {
+            return None;
+        }
+        let rank = ((share.clamp(0.0, 1.0) * self.total as f32).ceil() as u32).max(1);
+        let mut seen = 0;
+        let bin = self.counts.iter().position(|&count| {
+            seen += count;
+            seen >= rank
+        })?;
+        Some(MIN_LEVEL + (bin as f32 + 0.5) * BIN_WIDTH)
+    }
+}
+
+fn bin(level: f32) -> usize {
+    if level.is_nan() {
+        return 0;
+    }
+    (((level - MIN_LEVEL) / BIN_WIDTH).max(0.0) as usize).min(BIN_COUNT - 1)
+}
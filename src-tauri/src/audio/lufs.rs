@@ -0,0 +1,285 @@
+use std::{collections::VecDeque, f64::consts::PI};
+
+use serde::Serialize;
+
+use super::biquad::Biquad;
+
+/// Gating blocks and the short-term window advance in steps of this many seconds
+const STEP_SECONDS: f64 = 0.1;
+/// 400 ms
+const MOMENTARY_STEPS: usize = 4;
+/// 3 s
+const SHORT_TERM_STEPS: usize = 30;
+const ABSOLUTE_GATE: f64 = -70.0;
+const RELATIVE_GATE: f64 = -10.0;
+/// Resolution of the gating histogram in LU
+const BIN_WIDTH: f64 = 0.1;
+const BIN_COUNT: usize = 800;
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Lufs {
+    pub momentary: f32,
+    pub short_term: f32,
+    /// Gated over everything measured since the meter was created
+    pub integrated: f32,
+}
+
+impl Default for Lufs {
+    fn default() -> Self {
+        Self {
+            momentary: f32::NEG_INFINITY,
+            short_term: f32::NEG_INFINITY,
+            integrated: f32::NEG_INFINITY,
+        }
+    }
+}
+
+/// Loudness as specified by ITU-R BS.1770-4 and EBU R128, for a single channel
+pub struct LoudnessMeter {
+    k_weighting: [Biquad; 2],
+    samples_per_step: usize,
+    step_sum_squares: f64,
+    step_samples: usize,
+    /// Mean squares of the latest steps, newest last
+    steps: VecDeque<f64>,
+    /// Running sums of the latest momentary and short-term steps
+    momentary_sum: f64,
+    short_term_sum: f64,
+    gating: GatingHistogram,
+    lufs: Lufs,
+}
+
+impl LoudnessMeter {
+    pub fn new(sample_rate: f32) -> Self {
+        let fs = f64::from(sample_rate);
+        Self {
+            k_weighting: k_weighting(fs),
+            samples_per_step: (fs * STEP_SECONDS).round() as usize,
+            step_sum_squares: 0.0,
+            step_samples: 0,
+            steps: VecDeque::with_capacity(SHORT_TERM_STEPS + 1),
+            momentary_sum: 0.0,
+            short_term_sum: 0.0,
+            gating: GatingHistogram::default(),
+            lufs: Lufs::default(),
+        }
+    }
+
+    pub fn process(&mut self, samples: &[f32]) {
+        for &sample in samples {
+            let weighted = self
+                .k_weighting
+                .iter_mut()
+                .fold(f64::from(sample), |x, stage| stage.process(x));
+            self.step_sum_squares += weighted * weighted;
+            self.step_samples += 1;
+            if self.step_samples == self.samples_per_step {
+                self.finish_step();
+            }
+        }
+    }
+
+    pub fn lufs(&self) -> Lufs {
+        self.lufs
+    }
+
+    fn finish_step(&mut self) {
+        let step = self.step_sum_squares / self.step_samples as f64;
+        self.steps.push_back(step);
+        self.step_sum_squares = 0.0;
+        self.step_samples = 0;
+        self.momentary_sum += step;
+        self.short_term_sum += step;
+        if self.steps.len() > MOMENTARY_STEPS {
+            self.momentary_sum -= self.steps[self.steps.len() - 1 - MOMENTARY_STEPS];
+        }
+        if self.steps.len() > SHORT_TERM_STEPS {
+            self.short_term_sum -= self.steps.pop_front().unwrap_or_default();
+        }
+
+        let momentary = self.momentary_sum.max(0.0) / MOMENTARY_STEPS.min(self.steps.len()) as f64;
+        let short_term =
+            self.short_term_sum.max(0.0) / SHORT_TERM_STEPS.min(self.steps.len()) as f64;
+        // Gating blocks are momentary windows overlapping by 75%
+        if self.steps.len() >= MOMENTARY_STEPS {
+            self.gating.add(momentary);
+        }
+        self.lufs = Lufs {
+            momentary: loudness(momentary) as f32,
+            short_term: loudness(short_term) as f32,
+            integrated: self.gating.integrated() as f32,
+        };
+    }
+}
+
+fn loudness(mean_square: f64) -> f64 {
+    -0.691 + 10.0 * mean_square.log10()
+}
+
+/// The two stage K-weighting filter, with the coefficients from BS.1770
+/// recomputed for the actual sample rate
+fn k_weighting(sample_rate: f64) -> [Biquad; 2] {
+    // high shelf modelling the acoustic effect of the head
+    let f0 = 1_681.974_450_955_533;
+    let gain_db = 3.999_843_853_973_347;
+    let q = 0.707_175_236_955_419_6;
+    let k = (PI * f0 / sample_rate).tan();
+    let vh = 10_f64.powf(gain_db / 20.0);
+    let vb = vh.powf(0.499_666_774_154_541_6);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad::new(
+        [
+            (vh + vb * k / q + k * k) / a0,
+            2.0 * (k * k - vh) / a0,
+            (vh - vb * k / q + k * k) / a0,
+        ],
+        [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    );
+
+    // the "revised low-frequency B" high-pass
+    let f0 = 38.135_470_876_024_44;
+    let q = 0.500_327_037_323_877_3;
+    let k = (PI * f0 / sample_rate).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad::new(
+        [1.0, -2.0, 1.0],
+        [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    );
+
+    [shelf, high_pass]
+}
+
+/// Counts and sums gating block powers by loudness, so integrated loudness
+/// doesn't need to remember every block of the night
+struct GatingHistogram {
+    counts: Vec<u64>,
+    sums: Vec<f64>,
+}
+
+impl Default for GatingHistogram {
+    fn default() -> Self {
+        Self {
+            counts: vec![0; BIN_COUNT],
+            sums: vec![0.0; BIN_COUNT],
+        }
+    }
+}
+
+impl GatingHistogram {
+    fn add(&mut self, mean_square: f64) {
+        let lufs = loudness(mean_square);
+        if lufs.is_nan() || lufs <= ABSOLUTE_GATE {
+            return;
+        }
+        let bin = (((lufs - ABSOLUTE_GATE) / BIN_WIDTH) as usize).min(BIN_COUNT - 1);
+        self.counts[bin] += 1;
+        self.sums[bin] += mean_square;
+    }
+
+    fn integrated(&self) -> f64 {
+        let mean_from = |first_bin: usize| {
+            let count = self.counts[first_bin..].iter().sum::<u64>();
+            let sum = self.sums[first_bin..].iter().sum::<f64>();
+            (count > 0).then(|| sum / count as f64)
+        };
+        let Some(ungated) = mean_from(0) else {
+            return f64::NEG_INFINITY;
+        };
+        let relative_gate = loudness(ungated) + RELATIVE_GATE;
+        let first_bin = (((relative_gate - ABSOLUTE_GATE) / BIN_WIDTH)
+            .ceil()
+            .max(0.0) as usize)
+            .min(BIN_COUNT - 1);
+        mean_from(first_bin).map_or(f64::NEG_INFINITY, loudness)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::TAU;
+
+    use super::*;
+
+    /// `seconds` of a 997 Hz sine with `amplitude`, continuing from `start`
+    fn sine(sample_rate: f32, amplitude: f32, start: usize, seconds: f32) -> Vec<f32> {
+        let frames = (sample_rate * seconds) as usize;
+        (start..start + frames)
+            .map(|i| amplitude * (TAU * 997.0 * i as f32 / sample_rate).sin())
+            .collect()
+    }
+
+    fn assert_near(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 0.05,
+            "{actual} LUFS, not {expected}"
+        );
+    }
+
+    #[test]
+    fn full_scale_sine_is_minus_3_lufs() {
+        for sample_rate in [44_100.0, 48_000.0] {
+            let mut meter = LoudnessMeter::new(sample_rate);
+            meter.process(&sine(sample_rate, 1.0, 0, 5.0));
+            let lufs = meter.lufs();
+            assert_near(lufs.momentary, -3.01);
+            assert_near(lufs.short_term, -3.01);
+            assert_near(lufs.integrated, -3.01);
+        }
+    }
+
+    #[test]
+    fn level_follows_the_amplitude() {
+        let mut meter = LoudnessMeter::new(48_000.0);
+        meter.process(&sine(48_000.0, 0.1, 0, 5.0));
+        assert_near(meter.lufs().integrated, -23.01);
+    }
+
+    #[test]
+    fn silence_is_gated_out() {
+        let sample_rate = 48_000.0;
+        let mut meter = LoudnessMeter::new(sample_rate);
+        meter.process(&sine(sample_rate, 0.1, 0, 10.0));
+        meter.process(&vec![0.0; 10 * 48_000]);
+        meter.process(&sine(sample_rate, 0.1, 0, 10.0));
+        let integrated = meter.lufs().integrated;
+        // Only the few blocks overlapping silence pull it down a little
+        assert!((integrated + 23.01).abs() < 0.1, "{integrated}");
+    }
+
+    #[test]
+    fn quiet_parts_are_gated_out_relative_to_the_loud_ones() {
+        // 40 LU quieter, above the absolute gate but below the relative one
+        let sample_rate = 48_000.0;
+        let mut meter = LoudnessMeter::new(sample_rate);
+        meter.process(&sine(sample_rate, 0.1, 0, 10.0));
+        meter.process(&sine(sample_rate, 0.001, 480_000, 10.0));
+        let integrated = meter.lufs().integrated;
+        // Only the few blocks overlapping both parts pull it down a little
+        assert!((integrated + 23.01).abs() < 0.1, "{integrated}");
+    }
+
+    #[test]
+    fn windows_slide() {
+        let sample_rate = 48_000.0;
+        let mut meter = LoudnessMeter::new(sample_rate);
+        for _ in 0..100 {
+            // A long night of running sums
+            meter.process(&sine(sample_rate, 1.0, 0, 1.0));
+        }
+        meter.process(&sine(sample_rate, 0.01, 0, 0.5));
+        let lufs = meter.lufs();
+        assert_near(lufs.momentary, -43.01);
+        // Still mostly the loud part
+        assert!(lufs.short_term > -6.0, "{lufs:?}");
+        meter.process(&sine(sample_rate, 0.01, 0, 3.0));
+        assert_near(meter.lufs().short_term, -43.01);
+    }
+
+    #[test]
+    fn nothing_measured_is_minus_infinity() {
+        let mut meter = LoudnessMeter::new(48_000.0);
+        meter.process(&vec![0.0; 48_000]);
+        let integrated = meter.lufs().integrated;
+        assert!(integrated.is_infinite() && integrated < 0.0, "{integrated}");
+    }
+}
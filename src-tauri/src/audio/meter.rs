@@ -0,0 +1,218 @@
+use std::collections::VecDeque;
+
+use super::{
+    filter::FilterChain,
+    lufs::LoudnessMeter,
+    noise_floor::NoiseFloorTracker,
+    peak::PeakMeter,
+    spectrum::SpectrumAnalyzer,
+    time_weighting::TimeWeightingMeter,
+    vad::VoiceActivityDetector,
+    weighting::{Weighting, WeightingFilter},
+    Loudness, MeterSettings,
+};
+
+/// Everything measured from a single signal at a fixed sample rate
+pub struct Meter {
+    sample_rate: u32,
+    weighting: Weighting,
+    /// The user's filters, before any weighting
+    chain: FilterChain,
+    crowd_chain: FilterChain,
+    filter: WeightingFilter,
+    time_weighting: TimeWeightingMeter,
+    leq: LeqWindow,
+    /// Measures what's left after echo cancellation
+    crowd_filter: WeightingFilter,
+    crowd_leq: LeqWindow,
+    lufs: LoudnessMeter,
+    noise_floor: NoiseFloorTracker,
+    peak: PeakMeter,
+    spectrum: SpectrumAnalyzer,
+    vad: VoiceActivityDetector,
+}
+
+impl Meter {
+    pub fn new(sample_rate: u32, weighting: Weighting) -> Self {
+        Self {
+            sample_rate,
+            weighting,
+            chain: FilterChain::default(),
+            crowd_chain: FilterChain::default(),
+            filter: WeightingFilter::new(weighting, sample_rate as f32),
+            time_weighting: TimeWeightingMeter::new(sample_rate as f32),
+            leq: LeqWindow::default(),
+            crowd_filter: WeightingFilter::new(weighting, sample_rate as f32),
+            crowd_leq: LeqWindow::default(),
+            lufs: LoudnessMeter::new(sample_rate as f32),
+            noise_floor: NoiseFloorTracker::new(sample_rate),
+            peak: PeakMeter::new(),
+            spectrum: SpectrumAnalyzer::new(sample_rate as f32),
+            vad: VoiceActivityDetector::new(sample_rate as f32),
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// `crowd` is `samples` with the echo cancelled, if there's a reference to cancel it with
+    pub fn process(
+        &mut self,
+        samples: &[f32],
+        crowd: Option<&[f32]>,
+        settings: &MeterSettings,
+    ) -> Loudness {
+        if settings.weighting != self.weighting {
+            // Levels measured with another weighting don't belong in the same windows
+            self.weighting = settings.weighting;
+            self.filter = WeightingFilter::new(self.weighting, self.sample_rate as f32);
+            self.time_weighting = TimeWeightingMeter::new(self.sample_rate as f32);
+            self.leq = LeqWindow::default();
+            self.crowd_filter = WeightingFilter::new(self.weighting, self.sample_rate as f32);
+            self.crowd_leq = LeqWindow::default();
+            self.vad = VoiceActivityDetector::new(self.sample_rate as f32);
+            self.noise_floor = NoiseFloorTracker::new(self.sample_rate);
+        }
+        self.chain
+            .update(&settings.filters, self.sample_rate as f32);
+        let samples = self.chain.process(samples);
+
+        let sum_squares = samples
+            .iter()
+            .map(|&x| {
+                let square = self.filter.process(x).powi(2);
+                self.time_weighting.process(square);
+                square
+            })
+            .sum::<f32>();
+        let target_frames = (self.sample_rate as f32 * settings.rms_seconds).round() as usize;
+        let mean_square = self.leq.push(sum_squares, samples.len(), target_frames);
+        let noise_floor = self.noise_floor.push(sum_squares, samples.len());
+        self.lufs.process(samples);
+
+        // Conversation is easier to pick out without the music
+        let (crowd, voice_activity) = if let Some(crowd) = crowd {
+            self.crowd_chain
+                .update(&settings.filters, self.sample_rate as f32);
+            let crowd = self.crowd_chain.process(crowd);
+            let crowd_sum_squares = crowd
+                .iter()
+                .map(|&x| self.crowd_filter.process(x).powi(2))
+                .sum::<f32>();
+            let mean_square = self
+                .crowd_leq
+                .push(crowd_sum_squares, crowd.len(), target_frames);
+            (
+                Some(10.0 * mean_square.log10()),
+                self.vad.process(crowd, crowd_sum_squares, target_frames),
+            )
+        } else {
+            self.crowd_leq = LeqWindow::default();
+            (None, self.vad.process(samples, sum_squares, target_frames))
+        };
+
+        let rms = mean_square.sqrt().clamp(0.0, 1.0);
+        Loudness {
+            loudness: 20.0 * rms.log10(),
+            spl: false,
+            crowd,
+            voice_activity,
+            noise_floor,
+            direction: None,
+            weighting: self.weighting,
+            time_weighted: self.time_weighting.levels(),
+            lufs: self.lufs.lufs(),
+            peak: self.peak.process(samples),
+            spectrum: self.spectrum.process(samples, settings.bands),
+            gated: false,
+        }
+    }
+}
+
+/// Sliding window of whole blocks, at least `target_frames` long.
+/// Keeps running sums, so a push costs the same however long the window is.
+#[derive(Default)]
+pub(super) struct LeqWindow {
+    /// (sum of squares, frames) per block
+    blocks: VecDeque<(f64, usize)>,
+    /// In double precision, so adding and subtracting blocks all night doesn't drift
+    sum_squares: f64,
+    frames: usize,
+}
+
+impl LeqWindow {
+    /// Returns the mean square over the window
+    pub fn push(&mut self, sum_squares: f32, frames: usize, target_frames: usize) -> f32 {
+        let sum_squares = f64::from(sum_squares);
+        self.blocks.push_back((sum_squares, frames));
+        self.sum_squares += sum_squares;
+        self.frames += frames;
+        while let Some(&(oldest_sum_squares, oldest_frames)) = self.blocks.front() {
+            if self.frames - oldest_frames < target_frames.max(1) {
+                break;
+            }
+            self.blocks.pop_front();
+            self.sum_squares -= oldest_sum_squares;
+            self.frames -= oldest_frames;
+        }
+        if self.frames == 0 {
+            return 0.0;
+        }
+        (self.sum_squares.max(0.0) / self.frames as f64) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pushes a block of 100 frames at `level` in dBFS, returns the window's level
+    fn push(window: &mut LeqWindow, level: f32, target_frames: usize) -> f32 {
+        let mean_square = 10_f32.powf(level / 10.0);
+        10.0 * window.push(mean_square * 100.0, 100, target_frames).log10()
+    }
+
+    #[test]
+    fn averages_the_latest_blocks() {
+        let mut window = LeqWindow::default();
+        for _ in 0..10 {
+            push(&mut window, -20.0, 300);
+        }
+        // One block of three at 10 dB louder
+        let level = push(&mut window, -10.0, 300);
+        let expected = 10.0 * ((10_f32.powf(-1.0) + 2.0 * 10_f32.powf(-2.0)) / 3.0).log10();
+        assert!((level - expected).abs() < 0.01, "{level}, not {expected}");
+        push(&mut window, -20.0, 300);
+        let level = push(&mut window, -20.0, 300);
+        assert!((level - expected).abs() < 0.01, "{level}, not {expected}");
+        // Until it's pushed out
+        let level = push(&mut window, -20.0, 300);
+        assert!((level + 20.0).abs() < 0.01, "{level}");
+    }
+
+    #[test]
+    fn shrinks_with_the_target() {
+        let mut window = LeqWindow::default();
+        for _ in 0..10 {
+            push(&mut window, -40.0, 1000);
+        }
+        push(&mut window, -10.0, 1000);
+        let level = push(&mut window, -10.0, 200);
+        assert!((level + 10.0).abs() < 0.01, "{level}");
+    }
+
+    #[test]
+    fn running_sums_dont_drift() {
+        // A loud night doesn't leave anything behind in the quiet morning
+        let mut window = LeqWindow::default();
+        for _ in 0..1_000_000 {
+            push(&mut window, 0.0, 4800);
+        }
+        let mut level = 0.0;
+        for _ in 0..48 {
+            level = push(&mut window, -90.0, 4800);
+        }
+        assert!((level + 90.0).abs() < 0.01, "{level}");
+    }
+}
@@ -0,0 +1,128 @@
+use std::collections::VecDeque;
+
+use super::histogram::LevelHistogram;
+
+/// The floor is estimated from the levels of this many seconds
+const WINDOW_SECONDS: usize = 600;
+/// Before this many seconds were measured, the floor isn't known yet
+const MIN_SECONDS: usize = 10;
+/// Share of the time that's at or below the floor. Low enough that conversation
+/// and music don't count, high enough that a moment of silence doesn't either.
+const PERCENTILE: f32 = 0.1;
+
+/// Estimates the ambient noise floor as a low percentile of the Leq of every second
+pub(super) struct NoiseFloorTracker {
+    frames_per_second: usize,
+    sum_squares: f64,
+    frames: usize,
+    /// Levels of the latest seconds, oldest first
+    seconds: VecDeque<f32>,
+    histogram: LevelHistogram,
+    floor: Option<f32>,
+}
+
+impl NoiseFloorTracker {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            frames_per_second: sample_rate as usize,
+            sum_squares: 0.0,
+            frames: 0,
+            seconds: VecDeque::with_capacity(WINDOW_SECONDS + 1),
+            histogram: LevelHistogram::default(),
+            floor: None,
+        }
+    }
+
+    /// `sum_squares` is of the weighted samples of a block. Returns the floor in weighted dBFS.
+    pub fn push(&mut self, sum_squares: f32, frames: usize) -> Option<f32> {
+        self.sum_squares += f64::from(sum_squares);
+        self.frames += frames;
+        if self.frames >= self.frames_per_second {
+            let level = (10.0 * (self.sum_squares / self.frames as f64).log10()) as f32;
+            self.sum_squares = 0.0;
+            self.frames = 0;
+            self.seconds.push_back(level);
+            self.histogram.add(level);
+            if self.seconds.len() > WINDOW_SECONDS {
+                if let Some(oldest) = self.seconds.pop_front() {
+                    self.histogram.remove(oldest);
+                }
+            }
+            self.floor = if self.histogram.len() < MIN_SECONDS {
+                None
+            } else {
+                self.histogram.percentile(PERCENTILE)
+            };
+        }
+        self.floor
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Few frames per second, so a long window is quick to fill
+    const SAMPLE_RATE: u32 = 100;
+
+    /// Pushes a second at `level` in dBFS, in two blocks
+    fn push_second(tracker: &mut NoiseFloorTracker, level: f32) -> Option<f32> {
+        let frames = SAMPLE_RATE as usize / 2;
+        let sum_squares = 10_f32.powf(level / 10.0) * frames as f32;
+        tracker.push(sum_squares, frames);
+        tracker.push(sum_squares, frames)
+    }
+
+    fn assert_floor(floor: Option<f32>, expected: f32) {
+        let floor = floor.unwrap();
+        assert!((floor - expected).abs() < 0.1, "{floor}, not {expected}");
+    }
+
+    #[test]
+    fn unknown_at_first() {
+        let mut tracker = NoiseFloorTracker::new(SAMPLE_RATE);
+        for _ in 1..MIN_SECONDS {
+            assert_eq!(push_second(&mut tracker, -60.0), None);
+        }
+        assert_floor(push_second(&mut tracker, -60.0), -60.0);
+    }
+
+    #[test]
+    fn finds_the_floor_between_songs() {
+        let mut tracker = NoiseFloorTracker::new(SAMPLE_RATE);
+        let mut floor = None;
+        for second in 0..100 {
+            // Quiet between songs for a fifth of the time
+            let level = if second % 5 == 0 { -60.0 } else { -20.0 };
+            floor = push_second(&mut tracker, level);
+        }
+        assert_floor(floor, -60.0);
+    }
+
+    #[test]
+    fn moments_of_silence_dont_count() {
+        let mut tracker = NoiseFloorTracker::new(SAMPLE_RATE);
+        let mut floor = None;
+        for second in 0..100 {
+            let level = if second % 20 == 0 { -90.0 } else { -50.0 };
+            floor = push_second(&mut tracker, level);
+        }
+        assert_floor(floor, -50.0);
+    }
+
+    #[test]
+    fn old_seconds_are_forgotten() {
+        let mut tracker = NoiseFloorTracker::new(SAMPLE_RATE);
+        for _ in 0..WINDOW_SECONDS {
+            push_second(&mut tracker, -60.0);
+        }
+        let mut floor = None;
+        for second in 0..WINDOW_SECONDS {
+            floor = push_second(&mut tracker, -40.0);
+            if second < WINDOW_SECONDS * 9 / 10 {
+                assert_floor(floor, -60.0);
+            }
+        }
+        assert_floor(floor, -40.0);
+    }
+}
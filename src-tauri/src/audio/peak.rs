@@ -0,0 +1,105 @@
+use std::f32::consts::PI;
+
+use serde::Serialize;
+
+/// True-peak is measured by interpolating this many points per sample, as in BS.1770-4 annex 2
+const OVERSAMPLING: usize = 4;
+/// Input samples each phase of the interpolation filter looks at
+const TAPS_PER_PHASE: usize = 13;
+/// The interpolated signal lags the input by this many samples
+const DELAY: usize = TAPS_PER_PHASE / 2;
+
+/// Peak levels of one block of samples
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Peak {
+    /// Highest absolute sample in dBFS
+    pub sample: f32,
+    /// Highest absolute value of the reconstructed signal in dBTP,
+    /// catches peaks that fall between samples
+    pub true_peak: f32,
+    /// How far the sample peak sticks out above the RMS, in dB
+    pub crest_factor: f32,
+}
+
+impl Default for Peak {
+    fn default() -> Self {
+        Self {
+            sample: f32::NEG_INFINITY,
+            true_peak: f32::NEG_INFINITY,
+            crest_factor: 0.0,
+        }
+    }
+}
+
+pub struct PeakMeter {
+    /// `taps[phase][n]` weighs the sample `n` samples before the newest one
+    taps: [[f32; TAPS_PER_PHASE]; OVERSAMPLING],
+    /// Ring buffer of the latest samples
+    history: [f32; TAPS_PER_PHASE],
+    newest: usize,
+}
+
+impl PeakMeter {
+    pub fn new() -> Self {
+        // Hann windowed sinc low-pass at the original nyquist frequency
+        let half_width = (OVERSAMPLING * DELAY) as f32;
+        let coefficient = |m: f32| {
+            if m.abs() > half_width {
+                return 0.0;
+            }
+            let window = 0.5 + 0.5 * (PI * m / (half_width + 1.0)).cos();
+            let x = PI * m / OVERSAMPLING as f32;
+            let sinc = if m == 0.0 { 1.0 } else { x.sin() / x };
+            window * sinc
+        };
+        let mut taps = [[0.0; TAPS_PER_PHASE]; OVERSAMPLING];
+        for (phase, phase_taps) in taps.iter_mut().enumerate() {
+            for (n, tap) in phase_taps.iter_mut().enumerate() {
+                *tap =
+                    coefficient(phase as f32 + (OVERSAMPLING as f32) * (n as f32 - DELAY as f32));
+            }
+            // unity gain at DC for every phase, so slow signals aren't overestimated
+            let sum = phase_taps.iter().sum::<f32>();
+            for tap in phase_taps.iter_mut() {
+                *tap /= sum;
+            }
+        }
+        Self {
+            taps,
+            history: [0.0; TAPS_PER_PHASE],
+            newest: 0,
+        }
+    }
+
+    pub fn process(&mut self, samples: &[f32]) -> Peak {
+        let mut sample_peak = 0.0_f32;
+        let mut true_peak = 0.0_f32;
+        let mut sum_squares = 0.0;
+        for &sample in samples {
+            sample_peak = sample_peak.max(sample.abs());
+            sum_squares += sample * sample;
+            self.newest = (self.newest + 1) % TAPS_PER_PHASE;
+            self.history[self.newest] = sample;
+            for phase_taps in &self.taps {
+                let interpolated = phase_taps
+                    .iter()
+                    .enumerate()
+                    .map(|(n, tap)| {
+                        tap * self.history[(self.newest + TAPS_PER_PHASE - n) % TAPS_PER_PHASE]
+                    })
+                    .sum::<f32>();
+                true_peak = true_peak.max(interpolated.abs());
+            }
+        }
+        let rms = (sum_squares / samples.len() as f32).sqrt();
+        Peak {
+            sample: 20.0 * sample_peak.log10(),
+            true_peak: 20.0 * true_peak.max(sample_peak).log10(),
+            crest_factor: if rms > 0.0 {
+                20.0 * (sample_peak / rms).log10()
+            } else {
+                0.0
+            },
+        }
+    }
+}
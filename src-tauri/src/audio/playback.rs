@@ -0,0 +1,527 @@
+use std::{
+    fs::File,
+    io::BufReader,
+    path::Path,
+    sync::{mpsc, Arc, Mutex, MutexGuard, Weak},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use cpal::{
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+    Device, FromSample, SampleFormat, SizedSample, Stream, StreamConfig, StreamError,
+};
+use rodio::{
+    dynamic_mixer::{self, DynamicMixer, DynamicMixerController},
+    queue::SourcesQueueOutput,
+    source::EmptyCallback,
+    Decoder, Sink, Source,
+};
+use serde::Serialize;
+use tokio::sync::{oneshot, watch};
+
+use super::Playing;
+
+/// How often the stream thread looks for the device it's on disappearing
+/// or the preferred one coming back
+const DEVICE_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OutputDeviceInfo {
+    pub name: String,
+    pub is_default: bool,
+}
+
+pub fn list_output_devices() -> anyhow::Result<Vec<OutputDeviceInfo>> {
+    let host = cpal::default_host();
+    let default_name = host.default_output_device().and_then(|d| d.name().ok());
+    Ok(host
+        .output_devices()?
+        .filter_map(|device| device.name().ok())
+        .map(|name| OutputDeviceInfo {
+            is_default: default_name.as_ref() == Some(&name),
+            name,
+        })
+        .collect())
+}
+
+type Reply = oneshot::Sender<anyhow::Result<()>>;
+type Mixer = Arc<DynamicMixerController<f32>>;
+
+enum Command {
+    /// Opens the stream if it isn't
+    Open(Reply),
+    /// Reopens the stream on another device, `None` is the default output
+    SetDevice(Option<String>, Reply),
+    /// From the stream of the given generation, which stops playing
+    Failed(u64, StreamError),
+}
+
+/// What the players and the stream thread share
+#[derive(Default)]
+struct Shared {
+    /// Of the current stream, `None` while there's none
+    mixer: Option<Mixer>,
+    /// Added to `mixer`
+    voices: Vec<Weak<Voice>>,
+}
+
+impl Shared {
+    /// Voices on a stream that's gone never reach their end, they're over now
+    fn finish_voices(&mut self) {
+        for voice in self.voices.drain(..).filter_map(|voice| voice.upgrade()) {
+            voice.finish();
+        }
+    }
+}
+
+/// Plays sounds on a single long-lived output stream, mixing as many voices as are playing.
+/// Cheap to clone, every clone plays on the same stream.
+#[derive(Clone)]
+pub struct Playback {
+    shared: Arc<Mutex<Shared>>,
+    /// Streams only get a weak reference, so the thread stops with the last clone
+    commands: Arc<mpsc::Sender<Command>>,
+}
+
+impl Playback {
+    /// Plays on the output device called `preferred`, or the default output while that
+    /// isn't available. The stream is opened with the first sound.
+    pub fn new(preferred: Option<String>) -> Self {
+        let shared = Arc::new(Mutex::new(Shared::default()));
+        let (commands, commands_rx) = mpsc::channel();
+        let commands = Arc::new(commands);
+        let thread_shared = Arc::clone(&shared);
+        let thread_commands = Arc::downgrade(&commands);
+        // Streams aren't `Send` everywhere, so a thread of its own keeps them open
+        thread::spawn(move || {
+            let mut output = Output {
+                preferred,
+                stream: None,
+                generation: 0,
+                shared: thread_shared,
+                commands: thread_commands,
+            };
+            // Its own deadline, so a steady stream of commands doesn't put the check off
+            let mut check_at = Instant::now() + DEVICE_CHECK_INTERVAL;
+            loop {
+                match commands_rx.recv_timeout(check_at.saturating_duration_since(Instant::now())) {
+                    Ok(Command::Open(reply)) => {
+                        let opened = if output.stream.is_some() {
+                            Ok(())
+                        } else {
+                            output.open()
+                        };
+                        reply.send(opened).ok();
+                    }
+                    Ok(Command::SetDevice(name, reply)) => {
+                        output.preferred = name;
+                        reply.send(output.open()).ok();
+                    }
+                    Ok(Command::Failed(generation, e)) => {
+                        if generation == output.generation {
+                            output.fail(e);
+                        }
+                    }
+                    Err(mpsc::RecvTimeoutError::Timeout) => {}
+                    Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                }
+                if check_at <= Instant::now() {
+                    output.check();
+                    check_at = Instant::now() + DEVICE_CHECK_INTERVAL;
+                }
+            }
+        });
+        Self { shared, commands }
+    }
+
+    /// Sounds that are playing go quiet, the next ones play on `name`
+    pub async fn set_device(&self, name: Option<String>) -> anyhow::Result<()> {
+        self.request(|reply| Command::SetDevice(name, reply)).await
+    }
+
+    pub async fn play(&self, path: &Path) -> anyhow::Result<PlayHandle> {
+        let file = BufReader::new(
+            File::open(path).with_context(|| format!("Failed to open file {}", path.display()))?,
+        );
+        let source = Decoder::new(file)
+            .with_context(|| format!("Failed to decode file {}", path.display()))?;
+        self.play_source(source).await
+    }
+
+    async fn play_source<S>(&self, source: S) -> anyhow::Result<PlayHandle>
+    where
+        S: Source + Send + 'static,
+        f32: FromSample<S::Item>,
+        S::Item: rodio::Sample + Send,
+    {
+        let (sink, queue) = Sink::new_idle();
+
+        let (done_tx, done_rx) = watch::channel(false);
+        let voice = Arc::new(Voice {
+            playing: Mutex::new(Some(Playing::start())),
+            done: done_tx,
+        });
+        sink.append(source);
+        let finished = Arc::clone(&voice);
+        sink.append::<EmptyCallback<f32>>(EmptyCallback::new(Box::new(move || {
+            finished.finish();
+        })));
+        // Dropped if it fails, which also finishes the voice
+        let play_handle = PlayHandle {
+            sink,
+            voice,
+            done: done_rx,
+        };
+        self.add_voice(queue, &play_handle.voice)
+            .await
+            .context("Failed to add a voice")?;
+        Ok(play_handle)
+    }
+
+    /// Registered in the same lock the stream thread swaps streams in,
+    /// so no voice ends up on a stream that's gone
+    async fn add_voice(
+        &self,
+        queue: SourcesQueueOutput<f32>,
+        voice: &Arc<Voice>,
+    ) -> anyhow::Result<()> {
+        let mut opened = false;
+        loop {
+            {
+                let mut shared = self.lock()?;
+                if let Some(mixer) = shared.mixer.clone() {
+                    mixer.add(queue);
+                    shared.voices.retain(|voice| voice.strong_count() > 0);
+                    shared.voices.push(Arc::downgrade(voice));
+                    return Ok(());
+                }
+            }
+            anyhow::ensure!(!opened, "Output stream closed right after opening");
+            self.request(Command::Open).await?;
+            opened = true;
+        }
+    }
+
+    fn lock(&self) -> anyhow::Result<MutexGuard<'_, Shared>> {
+        self.shared
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Output stream lock poisoned: {e}"))
+    }
+
+    /// Awaited, so opening a device doesn't block the runtime or the main thread
+    async fn request(&self, command: impl FnOnce(Reply) -> Command) -> anyhow::Result<()> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.commands
+            .send(command(reply_tx))
+            .map_err(|_| anyhow::anyhow!("Output stream thread stopped"))?;
+        reply_rx.await.context("Output stream thread stopped")?
+    }
+}
+
+/// The stream thread's state
+struct Output {
+    preferred: Option<String>,
+    /// With the name of the device it's on
+    stream: Option<(Stream, String)>,
+    /// Of the latest stream, so errors of replaced ones are ignored
+    generation: u64,
+    shared: Arc<Mutex<Shared>>,
+    commands: Weak<mpsc::Sender<Command>>,
+}
+
+impl Output {
+    /// Falls back to the default output when the preferred device isn't there or fails
+    fn open(&mut self) -> anyhow::Result<()> {
+        self.set_stream(None);
+        let host = cpal::default_host();
+        if let Some(preferred) = self.preferred.clone() {
+            let device = host
+                .output_devices()
+                .context("Failed to list output devices")?
+                .find(|device| device.name().is_ok_and(|name| name == preferred));
+            match device.map(|device| self.open_device(&device)) {
+                Some(Ok((stream, mixer))) => {
+                    log::info!("Playing on output device {preferred}");
+                    self.set_stream(Some((stream, preferred, mixer)));
+                    return Ok(());
+                }
+                Some(Err(e)) => log::warn!(
+                    "{:?}",
+                    e.context(format!(
+                        "Failed to open output device {preferred}, using the default"
+                    ))
+                ),
+                None => log::warn!("Output device {preferred} not found, using the default"),
+            }
+        }
+        let device = host
+            .default_output_device()
+            .context("No output device available")?;
+        let name = device.name().unwrap_or_default();
+        let (stream, mixer) = self
+            .open_device(&device)
+            .with_context(|| format!("Failed to open output device {name}"))?;
+        log::info!("Playing on default output device {name}");
+        self.set_stream(Some((stream, name, mixer)));
+        Ok(())
+    }
+
+    /// Like rodio's own stream, but its errors reach the stream thread
+    fn open_device(&mut self, device: &Device) -> anyhow::Result<(Stream, Mixer)> {
+        let supported_config = device.default_output_config()?;
+        let config = supported_config.config();
+        let (mixer, mixer_output) = dynamic_mixer::mixer(config.channels, config.sample_rate.0);
+        self.generation += 1;
+        let generation = self.generation;
+        let commands = self.commands.clone();
+        let on_error = move |e| {
+            if let Some(commands) = commands.upgrade() {
+                commands.send(Command::Failed(generation, e)).ok();
+            }
+        };
+        let stream = match supported_config.sample_format() {
+            SampleFormat::I8 => build_output_stream::<i8>(device, &config, mixer_output, on_error),
+            SampleFormat::I16 => {
+                build_output_stream::<i16>(device, &config, mixer_output, on_error)
+            }
+            SampleFormat::I32 => {
+                build_output_stream::<i32>(device, &config, mixer_output, on_error)
+            }
+            SampleFormat::I64 => {
+                build_output_stream::<i64>(device, &config, mixer_output, on_error)
+            }
+            SampleFormat::U8 => build_output_stream::<u8>(device, &config, mixer_output, on_error),
+            SampleFormat::U16 => {
+                build_output_stream::<u16>(device, &config, mixer_output, on_error)
+            }
+            SampleFormat::U32 => {
+                build_output_stream::<u32>(device, &config, mixer_output, on_error)
+            }
+            SampleFormat::U64 => {
+                build_output_stream::<u64>(device, &config, mixer_output, on_error)
+            }
+            SampleFormat::F32 => {
+                build_output_stream::<f32>(device, &config, mixer_output, on_error)
+            }
+            SampleFormat::F64 => {
+                build_output_stream::<f64>(device, &config, mixer_output, on_error)
+            }
+            sample_format => Err(anyhow::anyhow!("Unsupported sample format {sample_format}")),
+        }?;
+        stream.play()?;
+        Ok((stream, mixer))
+    }
+
+    /// Reopens when the device disappeared, or when the preferred one is back after a fallback
+    fn check(&mut self) {
+        let Some((_, current)) = &self.stream else {
+            // Opened with the next sound
+            return;
+        };
+        let Ok(devices) = cpal::default_host().output_devices() else {
+            return;
+        };
+        let names = devices
+            .filter_map(|device| device.name().ok())
+            .collect::<Vec<_>>();
+        let reopen = if names.contains(current) {
+            self.preferred
+                .as_ref()
+                .is_some_and(|preferred| preferred != current && names.contains(preferred))
+        } else {
+            log::warn!("Output device {current} disappeared");
+            true
+        };
+        if reopen {
+            if let Err(e) = self.open() {
+                log::error!("{:?}", e.context("Failed to reopen output stream"));
+            }
+        }
+    }
+
+    /// The stream is reopened with the next sound
+    fn fail(&mut self, e: StreamError) {
+        log::error!(
+            "{:?}",
+            anyhow::Error::from(e).context("Output stream failed")
+        );
+        self.set_stream(None);
+    }
+
+    fn set_stream(&mut self, stream: Option<(Stream, String, Mixer)>) {
+        let (stream, mixer) = match stream {
+            Some((stream, name, mixer)) => (Some((stream, name)), Some(mixer)),
+            None => (None, None),
+        };
+        if let Ok(mut shared) = self.shared.lock() {
+            // Cut off with the old stream, whichever way it's replaced
+            shared.finish_voices();
+            shared.mixer = mixer;
+        }
+        self.stream = stream;
+    }
+}
+
+fn build_output_stream<T>(
+    device: &Device,
+    config: &StreamConfig,
+    mut mixer_output: DynamicMixer<f32>,
+    on_error: impl FnMut(StreamError) + Send + 'static,
+) -> anyhow::Result<Stream>
+where
+    T: SizedSample + FromSample<f32>,
+{
+    let stream = device.build_output_stream(
+        config,
+        move |data: &mut [T], _| {
+            for sample in data {
+                *sample = T::from_sample_(mixer_output.next().unwrap_or(0.0));
+            }
+        },
+        on_error,
+        None,
+    )?;
+    Ok(stream)
+}
+
+/// What the sink's callback and the handle share
+struct Voice {
+    /// Gates the metering while audible, see [`super::PLAYBACKS`]
+    playing: Mutex<Option<Playing>>,
+    done: watch::Sender<bool>,
+}
+
+impl Voice {
+    fn finish(&self) {
+        self.set_playing(false);
+        self.done.send_replace(true);
+    }
+
+    fn set_playing(&self, playing: bool) {
+        if let Ok(mut current) = self.playing.lock() {
+            if !playing {
+                current.take();
+            } else if current.is_none() && !*self.done.borrow() {
+                *current = Some(Playing::start());
+            }
+        }
+    }
+}
+
+/// One voice playing in [`Playback`]. Dropping the handle stops it.
+pub struct PlayHandle {
+    sink: Sink,
+    voice: Arc<Voice>,
+    done: watch::Receiver<bool>,
+}
+
+impl PlayHandle {
+    pub fn stop(&self) {
+        self.sink.stop();
+        self.voice.finish();
+    }
+
+    pub fn pause(&self) {
+        self.sink.pause();
+        self.voice.set_playing(false);
+    }
+
+    pub fn resume(&self) {
+        self.voice.set_playing(true);
+        self.sink.play();
+    }
+
+    /// 1 is as loud as the file
+    pub fn set_volume(&self, volume: f32) {
+        self.sink.set_volume(volume.max(0.0));
+    }
+
+    pub fn is_finished(&self) -> bool {
+        *self.done.borrow()
+    }
+
+    /// Resolves once the sound played to the end or was stopped, also after the handle is
+    /// dropped. Unlike the handle, it can be kept while someone else owns the handle.
+    pub fn completion(&self) -> Completion {
+        Completion(self.done.clone())
+    }
+}
+
+impl Drop for PlayHandle {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+pub struct Completion(watch::Receiver<bool>);
+
+impl Completion {
+    pub async fn wait(mut self) {
+        // The sender lives as long as the voice, which finishes before it's dropped
+        self.0.wait_for(|&done| done).await.ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rodio::source::SineWave;
+
+    use super::*;
+
+    /// Plays into a mixer nothing reads, in place of a device
+    fn playback() -> (Playback, Output) {
+        let (mixer, _) = dynamic_mixer::mixer(1, 48_000);
+        let shared = Arc::new(Mutex::new(Shared {
+            mixer: Some(mixer),
+            voices: Vec::new(),
+        }));
+        let commands = Arc::new(mpsc::channel().0);
+        let output = Output {
+            preferred: None,
+            stream: None,
+            generation: 0,
+            shared: Arc::clone(&shared),
+            commands: Arc::downgrade(&commands),
+        };
+        (Playback { shared, commands }, output)
+    }
+
+    async fn assert_finished(play_handle: &PlayHandle) {
+        assert!(play_handle.is_finished());
+        assert!(play_handle.voice.playing.lock().unwrap().is_none());
+        tokio::time::timeout(Duration::from_secs(1), play_handle.completion().wait())
+            .await
+            .expect("completion should resolve");
+    }
+
+    #[tokio::test]
+    async fn switching_devices_finishes_voices() {
+        let (playback, mut output) = playback();
+        let play_handle = playback.play_source(SineWave::new(440.0)).await.unwrap();
+        assert!(!play_handle.is_finished());
+
+        // What opening another device starts with
+        output.set_stream(None);
+
+        assert_finished(&play_handle).await;
+        assert!(output.shared.lock().unwrap().voices.is_empty());
+    }
+
+    #[tokio::test]
+    async fn failing_stream_finishes_voices() {
+        let (playback, mut output) = playback();
+        let play_handles = [
+            playback.play_source(SineWave::new(440.0)).await.unwrap(),
+            playback.play_source(SineWave::new(880.0)).await.unwrap(),
+        ];
+
+        output.fail(StreamError::DeviceNotAvailable);
+
+        for play_handle in &play_handles {
+            assert_finished(play_handle).await;
+        }
+        assert!(output.shared.lock().unwrap().mixer.is_none());
+    }
+}
@@ -0,0 +1,97 @@
+use std::{
+    fs::File,
+    io::BufReader,
+    path::PathBuf,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use rodio::{Decoder, Source};
+use tokio::sync::watch;
+
+use super::{Input, LoudnessSource, MicStatus, RingWriter, Rings};
+
+/// Length of the blocks a recording is cut into
+const BLOCK_SECONDS: f32 = 0.1;
+/// How long to wait for the metering thread to catch up when its ring is full
+const FULL_RING_WAIT: Duration = Duration::from_millis(5);
+
+/// Replays a WAV, FLAC or MP3 recording as if it was heard by the microphone
+#[derive(Debug, Clone)]
+pub struct FileSource {
+    pub path: PathBuf,
+    /// 1.0 replays in real time, 10.0 ten times as fast. Rules still run on the
+    /// wall clock, and at high speeds the UI only sees some of the levels.
+    pub speed: f32,
+}
+
+impl LoudnessSource for FileSource {
+    fn start(self: Box<Self>, rings: Rings, status_tx: watch::Sender<MicStatus>) {
+        thread::spawn(move || {
+            let error = self
+                .replay(&rings, &status_tx)
+                .with_context(|| format!("Failed to replay {}", self.path.display()))
+                .err();
+            if let Some(e) = &error {
+                log::error!("{:?}", e);
+            }
+            status_tx.send_replace(MicStatus::Finished {
+                error: error.map(|e| format!("{e:#}")),
+            });
+        });
+    }
+}
+
+impl FileSource {
+    fn replay(&self, rings: &Rings, status_tx: &watch::Sender<MicStatus>) -> anyhow::Result<()> {
+        anyhow::ensure!(self.speed > 0.0, "Replay speed must be positive");
+        let file = BufReader::new(File::open(&self.path).context("Failed to open file")?);
+        let decoder = Decoder::new(file).context("Failed to decode file")?;
+        let sample_rate = decoder.sample_rate();
+        let channels = decoder.channels().max(1);
+        let frames_per_block = (sample_rate as f32 * BLOCK_SECONDS).round() as usize;
+        let block_duration = Duration::from_secs_f32(BLOCK_SECONDS / self.speed);
+        // mixed down like the microphone's channels
+        let mut writer = rings.open(Input::Mic(0), sample_rate, channels, None);
+        status_tx.send_replace(MicStatus::Connected {
+            device: self.path.display().to_string(),
+        });
+
+        let mut samples = decoder.convert_samples::<f32>();
+        let mut interleaved = Vec::with_capacity(frames_per_block * usize::from(channels));
+        let mut frames = 0;
+        let mut send_at = Instant::now();
+        loop {
+            interleaved.clear();
+            interleaved.extend(
+                samples
+                    .by_ref()
+                    .take(frames_per_block * usize::from(channels)),
+            );
+            if interleaved.is_empty() {
+                return Ok(());
+            }
+            send_at += block_duration;
+            thread::sleep(send_at.saturating_duration_since(Instant::now()));
+            // in recording time, however fast it's replayed
+            let captured = Duration::from_secs_f64(frames as f64 / f64::from(sample_rate));
+            if !write_all(&mut writer, &interleaved, captured) {
+                // nobody is measuring anymore
+                return Ok(());
+            }
+            frames += interleaved.len() / usize::from(channels);
+        }
+    }
+}
+
+/// Unlike a live stream, a recording can wait for the metering thread instead of losing samples
+fn write_all(writer: &mut RingWriter, interleaved: &[f32], captured: Duration) -> bool {
+    while !writer.write(interleaved.iter().copied(), captured) {
+        if writer.is_abandoned() {
+            return false;
+        }
+        thread::sleep(FULL_RING_WAIT);
+    }
+    true
+}
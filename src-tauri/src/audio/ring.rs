@@ -0,0 +1,232 @@
+use std::{sync::mpsc, thread::Thread, time::Duration};
+
+use rtrb::{Consumer, Producer, RingBuffer};
+
+use super::{health::SampleStats, Input};
+
+/// Samples a stream can be ahead of the metering thread, in seconds
+const RING_SECONDS: usize = 2;
+/// Callbacks a stream can be ahead of the metering thread
+const MAX_CHUNKS: usize = 1024;
+/// A later capture timestamp than this past the end of the previous callback means samples were lost
+const MAX_GAP: Duration = Duration::from_millis(20);
+
+/// Mono samples from one callback
+pub struct Block {
+    pub samples: Vec<f32>,
+    pub sample_rate: u32,
+    pub input: Input,
+    /// The first two channels as they are, when the main microphone has that many,
+    /// to tell where a sound comes from
+    pub stereo: Option<[Vec<f32>; 2]>,
+    /// When the first sample was captured, since the stream started
+    pub captured: Duration,
+    /// Of the raw samples of every channel
+    pub stats: SampleStats,
+}
+
+/// One callback's worth of interleaved samples
+#[derive(Debug, Clone, Copy)]
+struct Chunk {
+    frames: usize,
+    /// Of the first frame, since the stream started
+    captured: Duration,
+}
+
+/// Hands out a lock-free ring per stream and tells the metering thread about it
+#[derive(Clone)]
+pub struct Rings {
+    readers: mpsc::Sender<RingReader>,
+    worker: Thread,
+}
+
+impl Rings {
+    pub(super) fn new(readers: mpsc::Sender<RingReader>, worker: Thread) -> Self {
+        Self { readers, worker }
+    }
+
+    /// `channel` is the one that's measured, `None` mixes all of them down
+    pub fn open(
+        &self,
+        input: Input,
+        sample_rate: u32,
+        channels: u16,
+        channel: Option<usize>,
+    ) -> RingWriter {
+        let (writer, reader) = ring(input, sample_rate, channels, channel, self.worker.clone());
+        // The metering thread outlives every stream, so this only fails while shutting down
+        self.readers.send(reader).ok();
+        self.worker.unpark();
+        writer
+    }
+}
+
+/// A ring for a single stream, the writer wakes up `worker` whenever there's something to read
+pub fn ring(
+    input: Input,
+    sample_rate: u32,
+    channels: u16,
+    channel: Option<usize>,
+    worker: Thread,
+) -> (RingWriter, RingReader) {
+    let channels = usize::from(channels.max(1));
+    let (samples_tx, samples_rx) = RingBuffer::new(sample_rate as usize * channels * RING_SECONDS);
+    let (chunks_tx, chunks_rx) = RingBuffer::new(MAX_CHUNKS);
+    let writer = RingWriter {
+        samples: samples_tx,
+        chunks: chunks_tx,
+        channels,
+        worker,
+    };
+    let reader = RingReader {
+        samples: samples_rx,
+        chunks: chunks_rx,
+        channels,
+        channel,
+        next_capture: None,
+        block: Block {
+            samples: Vec::new(),
+            sample_rate,
+            input,
+            // Only the main microphone is located, see `DirectionFinder`
+            stereo: (input == Input::Mic(0) && channels >= 2).then(|| [Vec::new(), Vec::new()]),
+            captured: Duration::ZERO,
+            stats: SampleStats::default(),
+        },
+    };
+    (writer, reader)
+}
+
+/// The stream's end of a ring. Writing never allocates, locks or blocks,
+/// so it's safe to do from an audio callback.
+pub struct RingWriter {
+    samples: Producer<f32>,
+    chunks: Producer<Chunk>,
+    channels: usize,
+    worker: Thread,
+}
+
+impl RingWriter {
+    /// Writes one callback's worth of interleaved samples captured at `captured`,
+    /// measured from the start of the stream. When the metering thread has fallen
+    /// that far behind, the whole callback is dropped and `false` returned.
+    pub fn write(
+        &mut self,
+        samples: impl ExactSizeIterator<Item = f32>,
+        captured: Duration,
+    ) -> bool {
+        let len = samples.len();
+        if self.chunks.is_full() || self.samples.slots() < len {
+            return false;
+        }
+        let Ok(chunk) = self.samples.write_chunk_uninit(len) else {
+            return false;
+        };
+        chunk.fill_from_iter(samples);
+        // Samples first, so the reader always finds all of them once it sees the chunk
+        self.chunks
+            .push(Chunk {
+                frames: len / self.channels,
+                captured,
+            })
+            .ok();
+        self.worker.unpark();
+        true
+    }
+
+    /// The metering thread stopped reading
+    pub fn is_abandoned(&self) -> bool {
+        self.chunks.is_abandoned()
+    }
+}
+
+impl Drop for RingWriter {
+    fn drop(&mut self) {
+        // So the reader is noticed to be finished right away
+        self.worker.unpark();
+    }
+}
+
+/// The metering thread's end of a ring, turning interleaved samples back into [`Block`]s
+pub struct RingReader {
+    samples: Consumer<f32>,
+    chunks: Consumer<Chunk>,
+    channels: usize,
+    channel: Option<usize>,
+    /// Where the next chunk is expected to start, to notice lost samples
+    next_capture: Option<Duration>,
+    /// Reused for every chunk, so reading doesn't allocate once the buffers have grown
+    block: Block,
+}
+
+impl RingReader {
+    pub fn input(&self) -> Input {
+        self.block.input
+    }
+
+    /// The next callback's worth of samples, `None` until there is one
+    pub fn read(&mut self) -> Option<&Block> {
+        let chunk = self.chunks.pop().ok()?;
+        let len = chunk.frames * self.channels;
+        let read = self
+            .samples
+            .read_chunk(len)
+            .expect("Samples are written before their chunk");
+        let (first, second) = read.as_slices();
+
+        let Block {
+            samples,
+            stereo,
+            captured,
+            sample_rate,
+            input,
+            stats,
+        } = &mut self.block;
+        *stats = SampleStats::of(first.iter().chain(second));
+        let frames = || {
+            first
+                .chunks_exact(self.channels)
+                .chain(second.chunks_exact(self.channels))
+        };
+        samples.clear();
+        samples.extend(frames().map(|frame| match self.channel {
+            Some(channel) => valid(frame[channel]),
+            None => frame.iter().copied().map(valid).sum::<f32>() / self.channels as f32,
+        }));
+        if let Some(stereo) = stereo {
+            for (channel, samples) in stereo.iter_mut().enumerate() {
+                samples.clear();
+                samples.extend(frames().map(|frame| valid(frame[channel])));
+            }
+        }
+        read.commit_all();
+
+        if let Some(gap) = self
+            .next_capture
+            .and_then(|next_capture| chunk.captured.checked_sub(next_capture))
+            .filter(|&gap| gap > MAX_GAP)
+        {
+            log::warn!("Lost {gap:?} of samples on {input:?}");
+        }
+        *captured = chunk.captured;
+        self.next_capture = Some(
+            chunk.captured + Duration::from_secs_f64(chunk.frames as f64 / f64::from(*sample_rate)),
+        );
+        Some(&self.block)
+    }
+
+    /// The stream was closed and everything it wrote has been read
+    pub fn is_finished(&self) -> bool {
+        self.chunks.is_abandoned() && self.chunks.is_empty()
+    }
+}
+
+/// A single NaN would stay in every filter's state forever, so invalid samples
+/// are measured as silence. [`SampleStats`] still counts them.
+fn valid(sample: f32) -> f32 {
+    if sample.is_finite() {
+        sample
+    } else {
+        0.0
+    }
+}
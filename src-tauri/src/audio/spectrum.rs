@@ -0,0 +1,187 @@
+use std::{collections::VecDeque, f32::consts::PI, sync::Arc};
+
+use realfft::{num_complex::Complex, RealFftPlanner, RealToComplex};
+use serde::{Deserialize, Serialize};
+
+/// Samples per FFT, about 170 ms at 48 kHz. Gives bins narrow enough
+/// to still tell the lowest third-octave bands apart.
+const FFT_SIZE: usize = 8192;
+/// Third-octave bands from 25 Hz to 20 kHz, as `n` in `1000 * 10^(n / 10)`
+const FIRST_BAND: i32 = -16;
+const BAND_COUNT: usize = 30;
+/// Third-octave bands whose centers lie in these ranges make up the named levels
+const BASS: (f32, f32) = (20.0, 160.0);
+const VOICE: (f32, f32) = (250.0, 3200.0);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Bands {
+    Octave,
+    #[default]
+    ThirdOctave,
+}
+
+/// Unweighted level of one frequency band
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Band {
+    /// Exact center frequency in Hz, nominal labels are up to the UI
+    pub center: f32,
+    /// dBFS, or dB SPL once calibrated
+    pub level: f32,
+}
+
+/// Unweighted band levels of the latest `FFT_SIZE` samples
+#[derive(Debug, Clone, Copy)]
+pub struct Spectrum {
+    pub resolution: Bands,
+    /// Mean square per third-octave band
+    third_octave: [f32; BAND_COUNT],
+    /// Added to every level, see [`Spectrum::calibrated`]
+    offset: f32,
+}
+
+impl Default for Spectrum {
+    fn default() -> Self {
+        Self {
+            resolution: Bands::default(),
+            third_octave: [0.0; BAND_COUNT],
+            offset: 0.0,
+        }
+    }
+}
+
+impl Spectrum {
+    /// Levels in dB SPL instead of dBFS, given the microphone's offset between them
+    pub fn calibrated(self, offset: f32) -> Self {
+        Self { offset, ..self }
+    }
+
+    /// Levels in the selected resolution, lowest first
+    pub fn bands(&self) -> Vec<Band> {
+        match self.resolution {
+            Bands::ThirdOctave => (0..BAND_COUNT)
+                .map(|i| Band {
+                    center: third_octave_center(i),
+                    level: decibels(self.third_octave[i]) + self.offset,
+                })
+                .collect(),
+            // Every octave is exactly three third-octaves
+            Bands::Octave => self
+                .third_octave
+                .chunks(3)
+                .enumerate()
+                .map(|(i, powers)| Band {
+                    center: third_octave_center(3 * i + 1),
+                    level: decibels(powers.iter().sum()) + self.offset,
+                })
+                .collect(),
+        }
+    }
+
+    /// Level of the low end: bass, kick drums, subwoofers
+    pub fn bass(&self) -> f32 {
+        self.level_between(BASS.0, BASS.1)
+    }
+
+    /// Level of the range most speech energy is in, i.e. chatter
+    pub fn voice(&self) -> f32 {
+        self.level_between(VOICE.0, VOICE.1)
+    }
+
+    /// Combined level of the third-octave bands centered in `[low, high)` Hz
+    pub fn level_between(&self, low: f32, high: f32) -> f32 {
+        decibels(
+            (0..BAND_COUNT)
+                .filter(|&i| (low..high).contains(&third_octave_center(i)))
+                .map(|i| self.third_octave[i])
+                .sum(),
+        ) + self.offset
+    }
+}
+
+fn third_octave_center(index: usize) -> f32 {
+    1000.0 * 10_f32.powf((FIRST_BAND + index as i32) as f32 / 10.0)
+}
+
+fn decibels(mean_square: f32) -> f32 {
+    10.0 * mean_square.log10()
+}
+
+pub struct SpectrumAnalyzer {
+    fft: Arc<dyn RealToComplex<f32>>,
+    window: Vec<f32>,
+    /// Converts a bin's squared magnitude into its share of the mean square
+    normalization: f32,
+    /// `[first, last)` bin of each third-octave band
+    band_bins: [(usize, usize); BAND_COUNT],
+    history: VecDeque<f32>,
+    input: Vec<f32>,
+    output: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+}
+
+impl SpectrumAnalyzer {
+    pub fn new(sample_rate: f32) -> Self {
+        let fft = RealFftPlanner::<f32>::new().plan_fft_forward(FFT_SIZE);
+        let window = (0..FFT_SIZE)
+            .map(|n| 0.5 - 0.5 * (2.0 * PI * n as f32 / FFT_SIZE as f32).cos())
+            .collect::<Vec<_>>();
+        // Parseval, with the window's energy taken out again
+        let normalization = 1.0 / (FFT_SIZE as f32 * window.iter().map(|w| w * w).sum::<f32>());
+
+        let bin_width = sample_rate / FFT_SIZE as f32;
+        let nyquist_bin = FFT_SIZE / 2;
+        let mut band_bins = [(0, 0); BAND_COUNT];
+        for (i, bins) in band_bins.iter_mut().enumerate() {
+            let center = third_octave_center(i);
+            let edge = |exponent: f32| {
+                ((center * 10_f32.powf(exponent) / bin_width).round() as usize).min(nyquist_bin)
+            };
+            *bins = (edge(-0.05), edge(0.05));
+        }
+
+        Self {
+            input: fft.make_input_vec(),
+            output: fft.make_output_vec(),
+            scratch: fft.make_scratch_vec(),
+            fft,
+            window,
+            normalization,
+            band_bins,
+            history: VecDeque::from(vec![0.0; FFT_SIZE]),
+        }
+    }
+
+    pub fn process(&mut self, samples: &[f32], resolution: Bands) -> Spectrum {
+        self.history.extend(samples);
+        let excess = self.history.len() - FFT_SIZE;
+        self.history.drain(..excess);
+
+        for ((input, sample), window) in self.input.iter_mut().zip(&self.history).zip(&self.window)
+        {
+            *input = sample * window;
+        }
+        if let Err(e) =
+            self.fft
+                .process_with_scratch(&mut self.input, &mut self.output, &mut self.scratch)
+        {
+            log::error!("FFT failed: {e}");
+            return Spectrum::default();
+        }
+
+        let mut third_octave = [0.0; BAND_COUNT];
+        for (power, &(first, last)) in third_octave.iter_mut().zip(&self.band_bins) {
+            // Each bin stands for its mirror image above nyquist as well
+            *power = 2.0
+                * self.normalization
+                * self.output[first..last]
+                    .iter()
+                    .map(Complex::norm_sqr)
+                    .sum::<f32>();
+        }
+        Spectrum {
+            resolution,
+            third_octave,
+            offset: 0.0,
+        }
+    }
+}
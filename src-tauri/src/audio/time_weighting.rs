@@ -0,0 +1,104 @@
+use serde::Serialize;
+
+/// Time constants from IEC 61672-1, in seconds
+const FAST: f64 = 0.125;
+const SLOW: f64 = 1.0;
+const IMPULSE_RISE: f64 = 0.035;
+const IMPULSE_FALL: f64 = 1.5;
+
+/// Exponentially time weighted levels in dBFS
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct TimeWeighted {
+    pub fast: f32,
+    pub slow: f32,
+    pub impulse: f32,
+}
+
+impl Default for TimeWeighted {
+    fn default() -> Self {
+        Self {
+            fast: f32::NEG_INFINITY,
+            slow: f32::NEG_INFINITY,
+            impulse: f32::NEG_INFINITY,
+        }
+    }
+}
+
+impl TimeWeighted {
+    /// Every level shifted by `offset` dB
+    pub fn offset(self, offset: f32) -> Self {
+        Self {
+            fast: self.fast + offset,
+            slow: self.slow + offset,
+            impulse: self.impulse + offset,
+        }
+    }
+}
+
+/// Runs the squared, frequency weighted signal through each time weighting.
+/// Unlike a boxcar window, a single bang fades out gradually instead of
+/// dropping out all at once.
+pub struct TimeWeightingMeter {
+    fast: Exponential,
+    slow: Exponential,
+    impulse: Exponential,
+    /// Used instead of the impulse's own constant while the level is falling
+    impulse_fall_retain: f64,
+}
+
+impl TimeWeightingMeter {
+    pub fn new(sample_rate: f32) -> Self {
+        let fs = f64::from(sample_rate);
+        Self {
+            fast: Exponential::new(FAST, fs),
+            slow: Exponential::new(SLOW, fs),
+            impulse: Exponential::new(IMPULSE_RISE, fs),
+            impulse_fall_retain: Exponential::new(IMPULSE_FALL, fs).retain,
+        }
+    }
+
+    pub fn process(&mut self, square: f32) {
+        let square = f64::from(square);
+        self.fast.process(square);
+        self.slow.process(square);
+        // impulse follows rises quickly but decays slowly
+        if square > self.impulse.value {
+            self.impulse.process(square);
+        } else {
+            self.impulse.process_with(square, self.impulse_fall_retain);
+        }
+    }
+
+    pub fn levels(&self) -> TimeWeighted {
+        let decibels = |mean_square: f64| (10.0 * mean_square.log10()) as f32;
+        TimeWeighted {
+            fast: decibels(self.fast.value),
+            slow: decibels(self.slow.value),
+            impulse: decibels(self.impulse.value),
+        }
+    }
+}
+
+/// First order low-pass on the mean square
+struct Exponential {
+    /// How much of the previous value remains after one sample
+    retain: f64,
+    value: f64,
+}
+
+impl Exponential {
+    fn new(time_constant: f64, sample_rate: f64) -> Self {
+        Self {
+            retain: (-1.0 / (time_constant * sample_rate)).exp(),
+            value: 0.0,
+        }
+    }
+
+    fn process(&mut self, square: f64) {
+        self.process_with(square, self.retain);
+    }
+
+    fn process_with(&mut self, square: f64, retain: f64) {
+        self.value = square + retain * (self.value - square);
+    }
+}
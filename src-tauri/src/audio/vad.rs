@@ -0,0 +1,251 @@
+use std::collections::VecDeque;
+
+use serde::Serialize;
+
+use super::{biquad::Biquad, meter::LeqWindow};
+
+/// Where most of the energy of speech is, and what telephones keep of it
+const SPEECH_BAND: (f64, f64) = (300.0, 3400.0);
+/// Speech is judged by how much its level jumps between frames this long
+const FRAME_SECONDS: f32 = 0.025;
+/// Syllables come a few times a second, so a second shows the rhythm of speech
+const MODULATION_SECONDS: f32 = 1.0;
+/// Blocks quieter than this are silence, in weighted dBFS
+const SILENCE_LEVEL: f32 = -70.0;
+/// Share of the energy that has to be in the speech band for speech
+const SPEECH_BAND_SHARE: f64 = 0.5;
+/// Standard deviation of frame levels in dB above which it's speech rather than music,
+/// which is a lot steadier
+const SPEECH_MODULATION: f32 = 4.0;
+/// Speech presence is the share of speech blocks over this many seconds
+const PRESENCE_SECONDS: f32 = 60.0;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub enum Activity {
+    #[default]
+    Silence,
+    Speech,
+    Music,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct VoiceActivity {
+    /// What the latest block sounds like
+    pub activity: Activity,
+    /// Leq of the latest speech blocks adding up to `rms_seconds`, ignoring everything else.
+    /// Minus infinity once nobody spoke for `rms_seconds`.
+    pub speech_loudness: f32,
+    /// Share of the last minute that was speech, from 0 to 1
+    pub speech_ratio: f32,
+}
+
+impl Default for VoiceActivity {
+    fn default() -> Self {
+        Self {
+            activity: Activity::Silence,
+            speech_loudness: f32::NEG_INFINITY,
+            speech_ratio: 0.0,
+        }
+    }
+}
+
+/// Tells speech from music and silence by level, how much of the energy is
+/// in the speech band, and how strongly the speech band level fluctuates
+pub struct VoiceActivityDetector {
+    band_pass: [Biquad; 2],
+    samples_per_frame: usize,
+    frame_sum_squares: f64,
+    frame_samples: usize,
+    /// Speech band levels of the latest frames in dBFS, newest last
+    frame_levels: VecDeque<f32>,
+    max_frames: usize,
+    speech: LeqWindow,
+    speech_loudness: f32,
+    /// Since the latest speech block
+    frames_without_speech: usize,
+    /// (is speech, frames) per block
+    presence: VecDeque<(bool, usize)>,
+    presence_frames: usize,
+    speech_frames: usize,
+    max_presence_frames: usize,
+}
+
+impl VoiceActivityDetector {
+    pub fn new(sample_rate: f32) -> Self {
+        let fs = f64::from(sample_rate);
+        let samples_per_frame = (sample_rate * FRAME_SECONDS).round() as usize;
+        Self {
+            band_pass: [
+                Biquad::high_pass(SPEECH_BAND.0, fs),
+                Biquad::low_pass(SPEECH_BAND.1, fs),
+            ],
+            samples_per_frame,
+            frame_sum_squares: 0.0,
+            frame_samples: 0,
+            frame_levels: VecDeque::new(),
+            max_frames: (MODULATION_SECONDS / FRAME_SECONDS).round() as usize,
+            speech: LeqWindow::default(),
+            speech_loudness: f32::NEG_INFINITY,
+            frames_without_speech: 0,
+            presence: VecDeque::new(),
+            presence_frames: 0,
+            speech_frames: 0,
+            max_presence_frames: (sample_rate * PRESENCE_SECONDS) as usize,
+        }
+    }
+
+    /// `weighted_sum_squares` is the sum of the frequency weighted `samples` squared,
+    /// the speech loudness is measured in the same weighting as everything else
+    pub fn process(
+        &mut self,
+        samples: &[f32],
+        weighted_sum_squares: f32,
+        target_frames: usize,
+    ) -> VoiceActivity {
+        let mut band_sum_squares = 0.0;
+        let mut total_sum_squares = 0.0;
+        for &sample in samples {
+            let x = f64::from(sample);
+            let band = self
+                .band_pass
+                .iter_mut()
+                .fold(x, |x, stage| stage.process(x));
+            band_sum_squares += band * band;
+            total_sum_squares += x * x;
+
+            self.frame_sum_squares += band * band;
+            self.frame_samples += 1;
+            if self.frame_samples == self.samples_per_frame {
+                let level = 10.0 * (self.frame_sum_squares / self.frame_samples as f64).log10();
+                // pauses between words count as a big jump, not an infinite one
+                self.frame_levels
+                    .push_back((level as f32).max(SILENCE_LEVEL));
+                if self.frame_levels.len() > self.max_frames {
+                    self.frame_levels.pop_front();
+                }
+                self.frame_sum_squares = 0.0;
+                self.frame_samples = 0;
+            }
+        }
+
+        let level = 10.0 * (weighted_sum_squares / samples.len() as f32).log10();
+        let activity = if level.is_nan() || level < SILENCE_LEVEL {
+            Activity::Silence
+        } else if band_sum_squares >= SPEECH_BAND_SHARE * total_sum_squares
+            && self.modulation() >= SPEECH_MODULATION
+        {
+            Activity::Speech
+        } else {
+            Activity::Music
+        };
+
+        let is_speech = activity == Activity::Speech;
+        if is_speech {
+            let mean_square = self
+                .speech
+                .push(weighted_sum_squares, samples.len(), target_frames);
+            self.speech_loudness = 10.0 * mean_square.log10();
+            self.frames_without_speech = 0;
+        } else {
+            self.frames_without_speech += samples.len();
+            if self.frames_without_speech >= target_frames {
+                // Speech from before that is no longer what the room sounds like
+                self.speech = LeqWindow::default();
+                self.speech_loudness = f32::NEG_INFINITY;
+            }
+        }
+        self.presence.push_back((is_speech, samples.len()));
+        self.presence_frames += samples.len();
+        if is_speech {
+            self.speech_frames += samples.len();
+        }
+        while self.presence_frames > self.max_presence_frames {
+            let Some((was_speech, frames)) = self.presence.pop_front() else {
+                break;
+            };
+            self.presence_frames -= frames;
+            if was_speech {
+                self.speech_frames -= frames;
+            }
+        }
+
+        VoiceActivity {
+            activity,
+            speech_loudness: self.speech_loudness,
+            speech_ratio: self.speech_frames as f32 / self.presence_frames.max(1) as f32,
+        }
+    }
+
+    /// Standard deviation of the latest frame levels in dB
+    fn modulation(&self) -> f32 {
+        let count = self.frame_levels.len() as f32;
+        if count < 2.0 {
+            return 0.0;
+        }
+        let mean = self.frame_levels.iter().sum::<f32>() / count;
+        let variance = self
+            .frame_levels
+            .iter()
+            .map(|level| (level - mean).powi(2))
+            .sum::<f32>()
+            / count;
+        variance.sqrt()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use super::*;
+
+    const SAMPLE_RATE: f32 = 48_000.0;
+    const BLOCK: usize = 4800;
+    /// `rms_seconds` of 1 s
+    const TARGET_FRAMES: usize = 48_000;
+
+    /// A 1 kHz tone switched on and off four times a second, like syllables
+    fn syllables(block: usize) -> Vec<f32> {
+        (block * BLOCK..(block + 1) * BLOCK)
+            .map(|i| {
+                let t = i as f32 / SAMPLE_RATE;
+                if (t * 4.0).fract() < 0.5 {
+                    0.1 * (2.0 * PI * 1000.0 * t).sin()
+                } else {
+                    0.0
+                }
+            })
+            .collect()
+    }
+
+    fn process(vad: &mut VoiceActivityDetector, samples: &[f32]) -> VoiceActivity {
+        let sum_squares = samples.iter().map(|x| x * x).sum();
+        vad.process(samples, sum_squares, TARGET_FRAMES)
+    }
+
+    #[test]
+    fn speech_is_forgotten_after_rms_seconds_without_it() {
+        let mut vad = VoiceActivityDetector::new(SAMPLE_RATE);
+        // Ends on a block where the tone is on the whole time
+        let speech = (0..31)
+            .map(|block| process(&mut vad, &syllables(block)))
+            .collect::<Vec<_>>();
+        let last = speech.last().unwrap();
+        assert_eq!(last.activity, Activity::Speech);
+        let loudness = last.speech_loudness;
+        // -23 dBFS while on, speech blocks are on at least half the time
+        assert!((-26.5..=-23.0).contains(&loudness), "{loudness} dBFS");
+
+        let silence = vec![0.0; BLOCK];
+        for _ in 0..9 {
+            let voice_activity = process(&mut vad, &silence);
+            assert_eq!(voice_activity.activity, Activity::Silence);
+        }
+        // Pauses shorter than `rms_seconds` keep it
+        assert!(vad.speech_loudness.is_finite());
+
+        let voice_activity = process(&mut vad, &silence);
+        assert!(voice_activity.speech_loudness.is_infinite());
+        assert!(voice_activity.speech_loudness < 0.0);
+    }
+}
@@ -0,0 +1,84 @@
+use std::{collections::HashMap, fs, path::PathBuf, time::Duration};
+
+use anyhow::Context;
+use tokio::{sync::watch, time::Instant};
+
+use crate::{audio::Loudness, json};
+
+/// How long the reference level is listened to
+const MEASURE_FOR: Duration = Duration::from_secs(5);
+
+/// The main microphone's latest uncalibrated measurement, not aggregated with any extra ones,
+/// and the device it was taken with
+pub type RawMeasurement = Option<(String, Loudness)>;
+
+/// Offsets from dBFS to dB SPL by input device name, stored as JSON
+pub struct Calibrations {
+    path: PathBuf,
+    offsets: HashMap<String, f32>,
+}
+
+impl Calibrations {
+    /// Starts out uncalibrated if there's nothing stored at `path` yet
+    pub fn load(path: PathBuf) -> Self {
+        let offsets = json::load(&path, "calibrations");
+        Self { path, offsets }
+    }
+
+    pub fn offset(&self, device: &str) -> Option<f32> {
+        self.offsets.get(device).copied()
+    }
+
+    pub fn offsets(&self) -> &HashMap<String, f32> {
+        &self.offsets
+    }
+
+    pub fn set(&mut self, device: String, offset: Option<f32>) -> anyhow::Result<()> {
+        match offset {
+            Some(offset) => self.offsets.insert(device, offset),
+            None => self.offsets.remove(&device),
+        };
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        fs::write(&self.path, serde_json::to_string_pretty(&self.offsets)?)
+            .with_context(|| format!("Failed to write calibrations {}", self.path.display()))
+    }
+}
+
+/// Listens to a steady reference tone, returns the device and its offset to `reference_level`.
+/// Calibrators play 1 kHz, where every weighting is 0 dB, so the weighted level is used.
+/// With a phone app as reference, both should use the same weighting.
+pub async fn measure(
+    mut raw: watch::Receiver<RawMeasurement>,
+    reference_level: f32,
+) -> anyhow::Result<(String, f32)> {
+    let end_at = Instant::now() + MEASURE_FOR;
+    let mut device: Option<String> = None;
+    let mut sum_power = 0.0;
+    let mut count = 0;
+    while let Ok(changed) = tokio::time::timeout_at(end_at, raw.changed()).await {
+        changed.context("Metering stopped")?;
+        let Some((current_device, loudness)) = raw.borrow_and_update().clone() else {
+            continue;
+        };
+        if device.get_or_insert_with(|| current_device.clone()) != &current_device {
+            anyhow::bail!("Input device changed during calibration");
+        }
+        if loudness.gated {
+            // Held from before our own sounds started, repeated while they play
+            continue;
+        }
+        sum_power += 10_f64.powf(f64::from(loudness.time_weighted.fast) / 10.0);
+        count += 1;
+    }
+    let device = device.context("No microphone connected")?;
+    anyhow::ensure!(
+        count > 0,
+        "Our own sounds were playing the whole time, nothing was measured"
+    );
+    let level = (10.0 * (sum_power / f64::from(count)).log10()) as f32;
+    anyhow::ensure!(level.is_finite(), "Heard nothing but silence");
+    Ok((device, reference_level - level))
+}
@@ -0,0 +1,168 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    audio::{ClipSettings, Metric, Side},
+    json,
+};
+
+const SETTINGS_FILE: &str = "settings.json";
+
+/// What was going on when a clip was saved, stored as JSON next to its WAV file
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ClipInfo {
+    /// The file name without extension, milliseconds since the Unix epoch
+    pub id: String,
+    pub recorded_at_ms: u64,
+    pub device: Option<String>,
+    pub metric: Metric,
+    /// Of `metric` when it became too loud
+    pub level: f32,
+    pub too_loud: Option<f32>,
+    pub side: Option<Side>,
+    #[serde(default)]
+    pub seconds: f32,
+}
+
+impl ClipInfo {
+    /// Stamped with the current time
+    pub fn new(
+        device: Option<String>,
+        metric: Metric,
+        level: f32,
+        too_loud: Option<f32>,
+        side: Option<Side>,
+    ) -> Self {
+        let recorded_at_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_millis() as u64);
+        Self {
+            id: recorded_at_ms.to_string(),
+            recorded_at_ms,
+            device,
+            metric,
+            level,
+            too_loud,
+            side,
+            seconds: 0.0,
+        }
+    }
+}
+
+/// Clips of incidents and their settings, in a directory of their own
+pub struct ClipStore {
+    dir: PathBuf,
+    settings: ClipSettings,
+}
+
+impl ClipStore {
+    /// Starts out with the default settings if there's nothing stored in `dir` yet
+    pub fn load(dir: PathBuf) -> Self {
+        let path = dir.join(SETTINGS_FILE);
+        let settings = json::load(&path, "clip settings");
+        Self { dir, settings }
+    }
+
+    pub fn settings(&self) -> ClipSettings {
+        self.settings
+    }
+
+    /// Also applies a lower retention limit right away
+    pub fn set_settings(&mut self, settings: ClipSettings) -> anyhow::Result<()> {
+        self.settings = settings;
+        self.create_dir()?;
+        let path = self.dir.join(SETTINGS_FILE);
+        fs::write(&path, serde_json::to_string_pretty(&settings)?)
+            .with_context(|| format!("Failed to write clip settings {}", path.display()))?;
+        self.prune()
+    }
+
+    /// Where the metering thread should write the clip of `info`
+    pub fn wav_path(&self, info: &ClipInfo) -> anyhow::Result<PathBuf> {
+        self.create_dir()?;
+        Ok(self.dir.join(format!("{}.wav", info.id)))
+    }
+
+    /// Once the WAV file is written, stores its metadata and deletes the oldest clips
+    pub fn save(&self, info: &ClipInfo) -> anyhow::Result<()> {
+        let path = self.dir.join(format!("{}.json", info.id));
+        fs::write(&path, serde_json::to_string_pretty(info)?)
+            .with_context(|| format!("Failed to write clip metadata {}", path.display()))?;
+        self.prune()
+    }
+
+    /// Newest first
+    pub fn list(&self) -> anyhow::Result<Vec<ClipInfo>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(anyhow::Error::from(e)
+                    .context(format!("Failed to read clips in {}", self.dir.display())))
+            }
+        };
+        let mut clips = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_none_or(|extension| extension != "json")
+                || path.file_name().is_some_and(|name| name == SETTINGS_FILE)
+            {
+                continue;
+            }
+            match read_info(&path) {
+                Ok(info) => clips.push(info),
+                Err(e) => log::error!("{:?}", e),
+            }
+        }
+        clips.sort_by_key(|clip| std::cmp::Reverse(clip.recorded_at_ms));
+        Ok(clips)
+    }
+
+    pub fn delete(&self, id: &str) -> anyhow::Result<()> {
+        for path in [self.path(id, "wav")?, self.path(id, "json")?] {
+            match fs::remove_file(&path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    return Err(anyhow::Error::from(e)
+                        .context(format!("Failed to delete {}", path.display())));
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Of one of the files of the clip `id`, e.g. its WAV file to play it
+    pub fn path(&self, id: &str, extension: &str) -> anyhow::Result<PathBuf> {
+        // Ids come from the frontend, they mustn't point outside the directory
+        anyhow::ensure!(
+            !id.is_empty() && id.chars().all(|c| c.is_ascii_digit()),
+            "Invalid clip id {id}"
+        );
+        Ok(self.dir.join(format!("{id}.{extension}")))
+    }
+
+    fn prune(&self) -> anyhow::Result<()> {
+        for clip in self.list()?.iter().skip(self.settings.max_clips) {
+            self.delete(&clip.id)?;
+        }
+        Ok(())
+    }
+
+    fn create_dir(&self) -> anyhow::Result<()> {
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create {}", self.dir.display()))
+    }
+}
+
+fn read_info(path: &Path) -> anyhow::Result<ClipInfo> {
+    let json = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read clip metadata {}", path.display()))?;
+    serde_json::from_str(&json)
+        .with_context(|| format!("Failed to parse clip metadata in {}", path.display()))
+}
@@ -0,0 +1,146 @@
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+/// A full dose is this long at the criterion level
+const CRITERION_SECONDS: f64 = 8.0 * 60.0 * 60.0;
+/// Levels below this many dBA don't add to the dose, like dosimeters set up
+/// for hearing conservation
+const THRESHOLD_LEVEL: f32 = 80.0;
+/// Longer gaps between levels, e.g. while the microphone is disconnected, aren't counted
+const MAX_STEP: Duration = Duration::from_secs(1);
+/// The projection follows the dose rate averaged over about this long
+const RATE_SECONDS: f64 = 60.0;
+
+/// How exposure is weighed against time
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ExposureModel {
+    /// 85 dBA for 8 hours, half the time for every 3 dB more
+    Niosh,
+    /// 90 dBA for 8 hours, half the time for every 5 dB more
+    Osha,
+}
+
+impl ExposureModel {
+    pub const ALL: [ExposureModel; 2] = [ExposureModel::Niosh, ExposureModel::Osha];
+
+    fn criterion_level(self) -> f32 {
+        match self {
+            ExposureModel::Niosh => 85.0,
+            ExposureModel::Osha => 90.0,
+        }
+    }
+
+    fn exchange_rate(self) -> f32 {
+        match self {
+            ExposureModel::Niosh => 3.0,
+            ExposureModel::Osha => 5.0,
+        }
+    }
+
+    /// Percent of a full dose per second at `level` in dBA
+    fn dose_rate(self, level: f32) -> f64 {
+        if level.is_nan() || level < THRESHOLD_LEVEL {
+            return 0.0;
+        }
+        let allowed_seconds = CRITERION_SECONDS
+            / 2f64.powf(f64::from(
+                (level - self.criterion_level()) / self.exchange_rate(),
+            ));
+        100.0 / allowed_seconds
+    }
+}
+
+/// Reported when a dose is reached, see [`crate::thresholds::Thresholds::exposure_limit`]
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct ExposureLimit {
+    pub model: ExposureModel,
+    pub dose_percent: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Dose {
+    pub model: ExposureModel,
+    /// 100 is the most that's considered safe for a day
+    pub percent: f32,
+    /// Until 100% at the recent rate, `None` while nothing adds to the dose
+    pub seconds_to_full: Option<f32>,
+}
+
+struct ModelDose {
+    model: ExposureModel,
+    percent: f64,
+    /// Percent per second, smoothed over [`RATE_SECONDS`]
+    rate: f64,
+}
+
+/// Integrates calibrated A-weighted levels into noise doses for every [`ExposureModel`]
+pub struct ExposureTracker {
+    doses: Vec<ModelDose>,
+    last_at: Option<Instant>,
+}
+
+impl Default for ExposureTracker {
+    fn default() -> Self {
+        Self {
+            doses: ExposureModel::ALL
+                .into_iter()
+                .map(|model| ModelDose {
+                    model,
+                    percent: 0.0,
+                    rate: 0.0,
+                })
+                .collect(),
+            last_at: None,
+        }
+    }
+}
+
+impl ExposureTracker {
+    /// `level` is in dBA SPL, as heard since the previous call
+    pub fn push(&mut self, level: f32, now: Instant) {
+        let step = self
+            .last_at
+            .map_or(Duration::ZERO, |last_at| {
+                now.saturating_duration_since(last_at)
+            })
+            .min(MAX_STEP)
+            .as_secs_f64();
+        self.last_at = Some(now);
+        let smoothing = (step / RATE_SECONDS).min(1.0);
+        for dose in &mut self.doses {
+            let rate = dose.model.dose_rate(level);
+            dose.percent += rate * step;
+            dose.rate += (rate - dose.rate) * smoothing;
+        }
+    }
+
+    /// Starts a new day
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    pub fn percent(&self, model: ExposureModel) -> f32 {
+        self.doses
+            .iter()
+            .find(|dose| dose.model == model)
+            .map_or(0.0, |dose| dose.percent as f32)
+    }
+
+    pub fn doses(&self) -> Vec<Dose> {
+        self.doses
+            .iter()
+            .map(|dose| Dose {
+                model: dose.model,
+                percent: dose.percent as f32,
+                seconds_to_full: if dose.percent >= 100.0 {
+                    Some(0.0)
+                } else if dose.rate > f64::EPSILON {
+                    Some(((100.0 - dose.percent) / dose.rate) as f32)
+                } else {
+                    None
+                },
+            })
+            .collect()
+    }
+}
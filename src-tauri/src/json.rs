@@ -0,0 +1,26 @@
+use std::{fs, path::Path};
+
+use anyhow::Context;
+use serde::de::DeserializeOwned;
+
+/// Reads what's stored as JSON at `path`, described as `what` in errors.
+/// Missing files are the default, unreadable ones are logged and the default as well.
+pub fn load<T: DeserializeOwned + Default>(path: &Path, what: &str) -> T {
+    let json = match fs::read_to_string(path) {
+        Ok(json) => json,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return T::default(),
+        Err(e) => {
+            log::error!(
+                "{:?}",
+                anyhow::Error::from(e).context(format!("Failed to read {what} {}", path.display()))
+            );
+            return T::default();
+        }
+    };
+    serde_json::from_str(&json)
+        .with_context(|| format!("Failed to parse {what} in {}", path.display()))
+        .unwrap_or_else(|e| {
+            log::error!("{e:?}");
+            T::default()
+        })
+}
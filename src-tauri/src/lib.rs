@@ -3,7 +3,13 @@
 #![warn(clippy::pedantic)]
 
 pub mod audio;
+pub mod calibration;
+pub mod clips;
+pub mod exposure;
+pub mod json;
+pub mod output_device;
 pub mod rules;
 pub mod sound_files;
 pub mod spotify;
+pub mod statistics;
 pub mod thresholds;
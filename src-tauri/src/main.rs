@@ -3,19 +3,25 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 use std::{
-    sync::OnceLock,
+    collections::HashMap,
+    sync::{Mutex, OnceLock},
     time::{Duration, Instant},
 };
 
 use decibender::{
-    audio::{self},
+    audio::{self, MicStatus},
+    calibration::{self, Calibrations, RawMeasurement},
+    clips::{ClipInfo, ClipStore},
+    exposure::{Dose, ExposureTracker},
+    output_device::OutputDevice,
     rules::RuleExecutor,
-    thresholds::Thresholds,
+    statistics::{LevelStatistics, WindowLevels},
+    thresholds::{BandLimitState, Thresholds},
 };
-use serde::{Deserialize, Serialize};
+use serde::Serialize;
 use tauri::{AppHandle, Manager};
 use tokio::{
-    sync::{broadcast, watch},
+    sync::{broadcast, oneshot, watch},
     task::JoinHandle,
 };
 
@@ -26,6 +32,13 @@ enum State {
     Acceptable,
 }
 
+#[derive(Clone, Serialize)]
+struct BandLimitEvent {
+    name: String,
+    level: f32,
+    breached: bool,
+}
+
 #[derive(Serialize)]
 struct AppError(String);
 
@@ -35,22 +48,275 @@ impl<T: ToString> From<T> for AppError {
     }
 }
 
-#[derive(Serialize, Clone, Copy)]
-struct Loudness {
-    loudness: f32,
+struct InputDevices(watch::Sender<audio::InputDevices>);
+
+/// Health of the main microphone, as last reported by the metering thread
+struct MicHealthStatus(watch::Sender<audio::MicHealth>);
+
+#[tauri::command]
+fn mic_health(status: tauri::State<'_, MicHealthStatus>) -> audio::MicHealth {
+    status.0.borrow().clone()
 }
 
-#[derive(Deserialize, Clone)]
-struct RmsSeconds {
-    rms_seconds: f32,
+/// Statistics of the thresholds' metric, as last sampled
+struct Statistics(watch::Sender<Vec<WindowLevels>>);
+
+#[tauri::command]
+fn statistics(statistics: tauri::State<'_, Statistics>) -> Vec<WindowLevels> {
+    statistics.0.borrow().clone()
+}
+
+/// Noise doses of the day so far, as last integrated
+struct Exposure(watch::Sender<Vec<Dose>>);
+
+#[tauri::command]
+fn exposure(exposure: tauri::State<'_, Exposure>) -> Vec<Dose> {
+    exposure.0.borrow().clone()
+}
+
+struct Clips {
+    store: Mutex<ClipStore>,
+    settings: watch::Sender<audio::ClipSettings>,
+}
+
+#[tauri::command]
+fn list_clips(clips: tauri::State<'_, Clips>) -> Result<Vec<ClipInfo>, AppError> {
+    Ok(clips.store.lock()?.list()?)
+}
+
+#[tauri::command]
+fn delete_clip(clips: tauri::State<'_, Clips>, id: String) -> Result<(), AppError> {
+    log::info!("Deleting clip {}", id);
+    Ok(clips.store.lock()?.delete(&id)?)
+}
+
+/// Async so the voice can be waited for on the runtime
+#[tauri::command]
+async fn play_clip(
+    clips: tauri::State<'_, Clips>,
+    playback: tauri::State<'_, audio::Playback>,
+    id: String,
+) -> Result<(), AppError> {
+    let path = clips.store.lock()?.path(&id, "wav")?;
+    let play_handle = playback.play(&path).await?;
+    // Playback stops when the handle is dropped
+    tokio::spawn(async move {
+        play_handle.completion().wait().await;
+        drop(play_handle);
+    });
+    Ok(())
+}
+
+#[tauri::command]
+fn clip_settings(clips: tauri::State<'_, Clips>) -> audio::ClipSettings {
+    *clips.settings.borrow()
+}
+
+#[tauri::command]
+fn set_clip_settings(
+    clips: tauri::State<'_, Clips>,
+    settings: audio::ClipSettings,
+) -> Result<(), AppError> {
+    log::info!("Updating clip settings: {:?}", settings);
+    clips.store.lock()?.set_settings(settings)?;
+    clips.settings.send_replace(settings);
+    Ok(())
+}
+
+/// Has the metering thread save what was heard around now, stores `info` once it's written
+fn save_clip(
+    app_handle: &AppHandle,
+    clip_requests: &std::sync::mpsc::Sender<audio::ClipRequest>,
+    mut info: ClipInfo,
+) -> anyhow::Result<()> {
+    let clips = app_handle.state::<Clips>();
+    let path = clips
+        .store
+        .lock()
+        .map_err(|e| anyhow::anyhow!("{e}"))?
+        .wav_path(&info)?;
+    let (done, done_rx) = oneshot::channel();
+    clip_requests
+        .send(audio::ClipRequest { path, done })
+        .map_err(|_| anyhow::anyhow!("Metering stopped"))?;
+    let app_handle = app_handle.clone();
+    tokio::spawn(async move {
+        let saved = async {
+            info.seconds = done_rx.await??.as_secs_f32();
+            app_handle
+                .state::<Clips>()
+                .store
+                .lock()
+                .map_err(|e| anyhow::anyhow!("{e}"))?
+                .save(&info)?;
+            app_handle.emit_all("clip", &info)?;
+            anyhow::Ok(())
+        };
+        if let Err(e) = saved.await {
+            log::error!("{:?}", e.context("Failed to save clip"));
+        }
+    });
+    Ok(())
+}
+
+struct Calibration {
+    calibrations: Mutex<Calibrations>,
+    raw: watch::Sender<RawMeasurement>,
+}
+
+#[tauri::command]
+fn list_calibrations(
+    calibration: tauri::State<'_, Calibration>,
+) -> Result<HashMap<String, f32>, AppError> {
+    Ok(calibration.calibrations.lock()?.offsets().clone())
+}
+
+/// Measures a reference level held to the current microphone and stores its offset
+#[tauri::command]
+async fn calibrate(
+    calibration: tauri::State<'_, Calibration>,
+    reference_level: f32,
+) -> Result<f32, AppError> {
+    log::info!("Calibrating to {} dB SPL", reference_level);
+    let (device, offset) =
+        calibration::measure(calibration.raw.subscribe(), reference_level).await?;
+    log::info!("Calibrated {} with offset {}", device, offset);
+    calibration.calibrations.lock()?.set(device, Some(offset))?;
+    Ok(offset)
+}
+
+#[tauri::command]
+fn clear_calibration(
+    calibration: tauri::State<'_, Calibration>,
+    device: String,
+) -> Result<(), AppError> {
+    log::info!("Clearing calibration of {}", device);
+    calibration.calibrations.lock()?.set(device, None)?;
+    Ok(())
+}
+
+#[tauri::command]
+fn set_input_channels(
+    input_devices: tauri::State<'_, InputDevices>,
+    channels: audio::Channels,
+) -> Result<(), AppError> {
+    log::info!("Setting input channels: {:?}", channels);
+    input_devices
+        .0
+        .send_modify(|devices| devices.channels = channels);
+    Ok(())
 }
 
 static INITIALIZED: OnceLock<()> = OnceLock::new();
 
+#[tauri::command]
+fn list_input_devices() -> Result<Vec<audio::InputDeviceInfo>, AppError> {
+    Ok(audio::list_input_devices()?)
+}
+
+#[tauri::command]
+fn set_input_device(
+    input_devices: tauri::State<'_, InputDevices>,
+    name: Option<String>,
+) -> Result<(), AppError> {
+    log::info!("Switching input device: {:?}", name);
+    input_devices
+        .0
+        .send_modify(|devices| devices.preferred = name);
+    Ok(())
+}
+
+#[tauri::command]
+fn list_output_devices() -> Result<Vec<audio::OutputDeviceInfo>, AppError> {
+    Ok(audio::list_output_devices()?)
+}
+
+/// `None` while playing on the default output
+#[tauri::command]
+fn output_device(
+    output_device: tauri::State<'_, Mutex<OutputDevice>>,
+) -> Result<Option<String>, AppError> {
+    Ok(output_device.lock()?.name().map(String::from))
+}
+
+/// Falls back to the default output whenever `name` isn't available, `None` to always use it
+#[tauri::command]
+async fn set_output_device(
+    output_device: tauri::State<'_, Mutex<OutputDevice>>,
+    playback: tauri::State<'_, audio::Playback>,
+    name: Option<String>,
+) -> Result<(), AppError> {
+    log::info!("Switching output device: {:?}", name);
+    output_device.lock()?.set(name.clone())?;
+    Ok(playback.set_device(name).await?)
+}
+
+/// Replaces every extra microphone, in the order their levels are reported in
+#[tauri::command]
+fn set_extra_mics(
+    input_devices: tauri::State<'_, InputDevices>,
+    mics: Vec<audio::ExtraMic>,
+) -> Result<(), AppError> {
+    log::info!("Setting extra mics: {:?}", mics);
+    input_devices
+        .0
+        .send_modify(|devices| devices.extra_mics = mics);
+    Ok(())
+}
+
+/// The output monitor to cancel the echo of music and announcements with, `None` to not cancel
+#[tauri::command]
+fn set_echo_reference(
+    input_devices: tauri::State<'_, InputDevices>,
+    name: Option<String>,
+) -> Result<(), AppError> {
+    log::info!("Setting echo reference: {:?}", name);
+    input_devices
+        .0
+        .send_modify(|devices| devices.echo_reference = name);
+    Ok(())
+}
+
+#[tauri::command]
+fn set_fallback_input_device(
+    input_devices: tauri::State<'_, InputDevices>,
+    name: Option<String>,
+) -> Result<(), AppError> {
+    log::info!("Setting fallback input device: {:?}", name);
+    input_devices
+        .0
+        .send_modify(|devices| devices.fallback = name);
+    Ok(())
+}
+
+/// Set `REPLAY_FILE` to run a recording through everything instead of the microphone,
+/// `REPLAY_SPEED` to replay it faster than real time
+fn loudness_source(input_devices: &InputDevices) -> Box<dyn audio::LoudnessSource> {
+    let Some(path) = std::env::var_os("REPLAY_FILE") else {
+        return Box::new(audio::CpalSource(input_devices.0.subscribe()));
+    };
+    let speed = std::env::var("REPLAY_SPEED")
+        .ok()
+        .and_then(|speed| speed.parse().ok())
+        .unwrap_or(1.0);
+    log::info!("Replaying {:?} at {}x speed", path, speed);
+    Box::new(audio::FileSource {
+        path: path.into(),
+        speed,
+    })
+}
+
 #[tauri::command]
 async fn init(
     app_handle: AppHandle,
-    initial_rms_seconds: f32,
+    input_devices: tauri::State<'_, InputDevices>,
+    calibration: tauri::State<'_, Calibration>,
+    mic_health_status: tauri::State<'_, MicHealthStatus>,
+    statistics_report: tauri::State<'_, Statistics>,
+    exposure_report: tauri::State<'_, Exposure>,
+    clips: tauri::State<'_, Clips>,
+    playback: tauri::State<'_, audio::Playback>,
+    initial_meter_settings: audio::MeterSettings,
     initial_thresholds: Thresholds,
 ) -> Result<(), AppError> {
     if let Err(_) = INITIALIZED.set(()) {
@@ -58,14 +324,37 @@ async fn init(
         return Ok(());
     }
     log::info!("Initializing");
+    initial_thresholds.validate()?;
 
-    let rule_executor = RuleExecutor::new(&app_handle).await?;
+    let rule_executor = RuleExecutor::new(&app_handle, playback.inner().clone()).await?;
 
-    app_handle.emit_all("thresholds", initial_thresholds)?;
-    tokio::spawn(rule_executor.clone().adjust_volume(initial_thresholds));
+    app_handle.emit_all("thresholds", initial_thresholds.clone())?;
+    tokio::spawn(
+        rule_executor
+            .clone()
+            .adjust_volume(initial_thresholds.clone(), None, None),
+    );
 
     let mut state = State::Acceptable;
     let mut end_grace_period_at = std::time::Instant::now();
+    let mut end_impulse_cooldown_at = std::time::Instant::now();
+    let mut current_device: Option<String> = None;
+    let mut calibration_offset: Option<f32> = None;
+    let mut noise_floor: Option<f32> = None;
+    let mut mic_healthy = true;
+    let mut statistics = LevelStatistics::default();
+    let mut statistics_metric = initial_thresholds.metric;
+    let mut exposure = ExposureTracker::default();
+    let mut exposure_limit_reached = false;
+    let mut next_exposure_report_at = Instant::now();
+    // While gated, metering holds the level from before our own sounds started
+    let mut gated_exposure_tick = tokio::time::interval(Duration::from_secs(1));
+    gated_exposure_tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let mut band_limit_states: Vec<BandLimitState> = initial_thresholds
+        .band_limits
+        .iter()
+        .map(|_| BandLimitState::default())
+        .collect();
     let mut current_task: Option<JoinHandle<()>> = None;
     let mut set_current_task = |task| {
         if let Some(task) = current_task.take() {
@@ -76,9 +365,22 @@ async fn init(
 
     let (louder_tx, mut louder_rx) = broadcast::channel::<()>(4);
     let (quieter_tx, mut quieter_rx) = broadcast::channel::<()>(4);
+    let (reset_exposure_tx, mut reset_exposure_rx) = broadcast::channel::<()>(4);
     let (thresholds_tx, mut thresholds_rx) = watch::channel::<Thresholds>(initial_thresholds);
-    let (rms_seconds_tx, rms_seconds) = watch::channel::<f32>(initial_rms_seconds);
-    let mut loudness_rx = audio::watch_loudness(rms_seconds)?;
+    let (meter_settings_tx, meter_settings) = watch::channel(initial_meter_settings);
+    let aggregation_settings = meter_settings.clone();
+    let audio::Metering {
+        loudness: mut loudness_rx,
+        impulses: mut impulse_rx,
+        mic_levels: mic_levels_rx,
+        mic_status: mut mic_status_rx,
+        mic_health: mut mic_health_rx,
+        clip_requests,
+    } = audio::watch_loudness(
+        meter_settings,
+        clips.settings.subscribe(),
+        loudness_source(&input_devices),
+    );
 
     app_handle.listen_global("louder", move |_event| {
         louder_tx.send(()).ok();
@@ -88,32 +390,41 @@ async fn init(
         quieter_tx.send(()).ok();
     });
 
-    app_handle.listen_global("rms-seconds", move |event| {
+    app_handle.listen_global("reset-exposure", move |_event| {
+        reset_exposure_tx.send(()).ok();
+    });
+
+    app_handle.listen_global("meter-settings", move |event| {
         let Some(payload) = event.payload() else {
-            log::error!("No payload in rms_seconds event");
+            log::error!("No payload in meter-settings event");
             return;
         };
-        let Ok(RmsSeconds { rms_seconds }) = serde_json::from_str(payload) else {
-            log::error!("Failed to parse rms_seconds payload: {}", payload);
+        let Ok(meter_settings) = serde_json::from_str(payload) else {
+            log::error!("Failed to parse meter-settings payload: {}", payload);
             return;
         };
-        log::info!("Updating rms_seconds: {}", rms_seconds);
-        rms_seconds_tx.send(rms_seconds).ok();
+        log::info!("Updating meter settings: {:?}", meter_settings);
+        meter_settings_tx.send(meter_settings).ok();
     });
     app_handle.listen_global("thresholds", move |event| {
         let Some(payload) = event.payload() else {
             log::error!("No payload in thresholds event");
             return;
         };
-        let Ok(thresholds) = serde_json::from_str(payload) else {
+        let Ok(thresholds) = serde_json::from_str::<Thresholds>(payload) else {
             log::error!("Failed to parse thresholds payload: {}", payload);
             return;
         };
+        if let Err(e) = thresholds.validate() {
+            log::error!("{:?}", e.context("Ignoring thresholds"));
+            return;
+        }
         log::info!("Updating thresholds: {:?}", thresholds);
         thresholds_tx.send(thresholds).ok();
     });
 
     loop {
+        let gated = loudness_rx.borrow().gated;
         tokio::select! {
             _ = louder_rx.recv() => {
                 end_grace_period_at = Instant::now() + Duration::from_secs(7);
@@ -125,36 +436,223 @@ async fn init(
                 tokio::spawn(rule_executor.clone().quieter());
                 continue;
             }
+            _ = reset_exposure_rx.recv() => {
+                log::info!("Resetting noise dose");
+                exposure.reset();
+                next_exposure_report_at = Instant::now();
+                continue;
+            }
             _ = thresholds_rx.changed() => {
-                let thresholds = *thresholds_rx.borrow_and_update();
-                app_handle.emit_all("thresholds", thresholds)?;
-                tokio::spawn(rule_executor.clone().adjust_volume(thresholds));
+                let thresholds = thresholds_rx.borrow_and_update().clone();
+                band_limit_states = thresholds
+                    .band_limits
+                    .iter()
+                    .map(|_| BandLimitState::default())
+                    .collect();
+                if thresholds.metric != statistics_metric {
+                    statistics_metric = thresholds.metric;
+                    statistics.reset();
+                }
+                app_handle.emit_all("thresholds", thresholds.clone())?;
+                tokio::spawn(rule_executor.clone().adjust_volume(
+                    thresholds,
+                    calibration_offset,
+                    noise_floor,
+                ));
+            }
+            Ok(impulse) = impulse_rx.recv() => {
+                app_handle.emit_all("impulse", impulse)?;
+                // While too loud, the punishment is already running
+                if !matches!(state, State::TooLoud) && end_impulse_cooldown_at <= Instant::now() {
+                    end_impulse_cooldown_at = Instant::now() + Duration::from_secs(10);
+                    tokio::spawn(rule_executor.clone().impulse());
+                }
+                continue;
+            }
+            _ = mic_status_rx.changed() => {
+                let mic_status = mic_status_rx.borrow_and_update().clone();
+                current_device = match &mic_status {
+                    MicStatus::Connected { device } => Some(device.clone()),
+                    _ => None,
+                };
+                app_handle.emit_all("mic-status", mic_status)?;
+                continue;
+            }
+            _ = mic_health_rx.changed() => {
+                let mic_health = mic_health_rx.borrow_and_update().clone();
+                if mic_health.is_healthy() != mic_healthy {
+                    mic_healthy = mic_health.is_healthy();
+                    if mic_healthy {
+                        log::info!("Microphone is healthy again");
+                    } else {
+                        log::warn!("Microphone is unhealthy: {:?}", mic_health.problems);
+                    }
+                }
+                mic_health_status.0.send_replace(mic_health.clone());
+                app_handle.emit_all("mic-health", mic_health)?;
+                continue;
             }
             _ = loudness_rx.changed() => {}
+            // Exposure goes on while our own sounds play, but no new levels arrive
+            _ = gated_exposure_tick.tick(), if gated => {}
         };
         let thresholds = thresholds_rx.borrow();
-        let loudness = *loudness_rx.borrow_and_update();
-        app_handle.emit_all("loudness", Loudness { loudness })?;
+        let raw = *loudness_rx.borrow_and_update();
+        calibration
+            .raw
+            .send_replace(current_device.clone().map(|device| (device, raw)));
+        let offset = match &current_device {
+            Some(device) => calibration.calibrations.lock()?.offset(device),
+            None => None,
+        };
+        let mut measurement = offset.map_or(raw, |offset| raw.calibrated(offset));
+        let mic_levels = {
+            let devices = input_devices.0.borrow();
+            let calibrations = calibration.calibrations.lock()?;
+            mic_levels_rx
+                .borrow()
+                .iter()
+                .map(|&level| {
+                    let device = match level.mic {
+                        0 => current_device.as_deref(),
+                        extra => devices
+                            .extra_mics
+                            .get(extra - 1)
+                            .map(|mic| mic.name.as_str()),
+                    };
+                    // By each device's own offset, from when it was calibrated as the main one
+                    match device.and_then(|device| calibrations.offset(device)) {
+                        Some(offset) => level.calibrated(offset),
+                        None => level,
+                    }
+                })
+                .collect::<Vec<_>>()
+        };
+        // Only levels in the same units and weighting as the main microphone's can be combined
+        let comparable = mic_levels
+            .iter()
+            .filter(|level| {
+                level.spl == measurement.spl && level.weighting == measurement.weighting
+            })
+            .copied()
+            .collect::<Vec<_>>();
+        if !comparable.is_empty() {
+            (measurement.loudness, measurement.time_weighted) =
+                audio::aggregate(aggregation_settings.borrow().aggregation, &comparable);
+        }
+        // The floor drifts all the time, only the first estimate is worth readjusting for
+        let first_noise_floor = noise_floor.is_none() && measurement.noise_floor.is_some();
+        noise_floor = measurement.noise_floor;
+        if offset != calibration_offset {
+            // Levels from before are in different units
+            statistics.reset();
+        }
+        if offset != calibration_offset || first_noise_floor {
+            calibration_offset = offset;
+            tokio::spawn(rule_executor.clone().adjust_volume(
+                thresholds.clone(),
+                calibration_offset,
+                noise_floor,
+            ));
+        }
+        app_handle.emit_all("mic-levels", mic_levels)?;
+        app_handle.emit_all("loudness", measurement)?;
+        app_handle.emit_all("spectrum", measurement.spectrum.bands())?;
+        // Doses are defined in dBA SPL. While gated, the held level is pushed once a second,
+        // short of what our own sounds add on top.
+        if measurement.spl && measurement.weighting == audio::Weighting::A {
+            exposure.push(measurement.time_weighted.slow, Instant::now());
+        }
+        if next_exposure_report_at <= Instant::now() {
+            next_exposure_report_at = Instant::now() + Duration::from_secs(1);
+            let doses = exposure.doses();
+            exposure_report.0.send_replace(doses.clone());
+            app_handle.emit_all("exposure", doses)?;
+            let reached = thresholds
+                .exposure_limit
+                .filter(|limit| exposure.percent(limit.model) >= limit.dose_percent);
+            if let Some(limit) = reached.filter(|_| !exposure_limit_reached) {
+                app_handle.emit_all("exposure-limit", limit)?;
+                tokio::spawn(rule_executor.clone().exposure_limit(limit));
+            }
+            // Warns again after a reset or once a raised limit is reached
+            exposure_limit_reached = reached.is_some();
+        }
+        if measurement.gated {
+            // Don't react to our own sounds
+            continue;
+        }
+        let now = Instant::now();
+        if statistics.push(measurement.get(thresholds.metric), now) {
+            let levels = statistics.levels();
+            statistics_report.0.send_replace(levels.clone());
+            app_handle.emit_all("statistics", levels)?;
+        }
+        for (limit, limit_state) in thresholds.band_limits.iter().zip(&mut band_limit_states) {
+            let level = limit.level(&measurement.spectrum);
+            if !limit_state.update(limit, thresholds.grace, level, now) {
+                continue;
+            }
+            app_handle.emit_all(
+                "band-limit",
+                BandLimitEvent {
+                    name: limit.name.clone(),
+                    level,
+                    breached: limit_state.breached,
+                },
+            )?;
+            // While too loud, the punishment is already running
+            if limit_state.breached && !matches!(state, State::TooLoud) {
+                tokio::spawn(rule_executor.clone().band_too_loud(limit.name.clone()));
+            }
+        }
         if end_grace_period_at > Instant::now() {
             continue;
         }
+        let loudness = match thresholds.statistic {
+            Some(statistic) => match statistics.get(statistic) {
+                Some(level) => level,
+                // Not measured long enough yet
+                None => continue,
+            },
+            None => measurement.get(thresholds.metric),
+        };
         match state {
-            State::Acceptable if thresholds.too_loud(loudness) => {
+            State::Acceptable if thresholds.too_loud(loudness, noise_floor) => {
                 end_grace_period_at = Instant::now() + Duration::from_secs(7);
-                set_current_task(tokio::spawn(rule_executor.clone().too_loud()));
+                let side = measurement.direction.and_then(audio::Direction::side);
+                if clips.settings.borrow().enabled {
+                    let info = ClipInfo::new(
+                        current_device.clone(),
+                        thresholds.metric,
+                        loudness,
+                        thresholds.too_loud_level(noise_floor),
+                        side,
+                    );
+                    if let Err(e) = save_clip(&app_handle, &clip_requests, info) {
+                        log::error!("{:?}", e.context("Failed to request clip"));
+                    }
+                }
+                set_current_task(tokio::spawn(rule_executor.clone().too_loud(side)));
                 state = State::TooLoud;
             }
-            State::Acceptable if thresholds.too_quiet(loudness) => {
+            // A dead or broken input would look like a quiet room, and the speech level is
+            // minus infinity while nobody is talking
+            State::Acceptable
+                if mic_healthy
+                    && loudness.is_finite()
+                    && thresholds.too_quiet(loudness, noise_floor) =>
+            {
                 end_grace_period_at = Instant::now() + Duration::from_secs(7);
                 set_current_task(tokio::spawn(rule_executor.clone().too_quiet()));
                 state = State::TooQuiet;
             }
-            State::TooLoud if thresholds.acceptable_from_too_loud(loudness) => {
+            State::TooLoud if thresholds.acceptable_from_too_loud(loudness, noise_floor) => {
                 end_grace_period_at = Instant::now() + Duration::from_secs(7);
                 set_current_task(tokio::spawn(rule_executor.clone().acceptable()));
                 state = State::Acceptable;
             }
-            State::TooQuiet if thresholds.acceptable_from_too_quiet(loudness) => {
+            State::TooQuiet if thresholds.acceptable_from_too_quiet(loudness, noise_floor) => {
                 end_grace_period_at = Instant::now() + Duration::from_secs(7);
                 set_current_task(tokio::spawn(rule_executor.clone().acceptable()));
                 state = State::Acceptable;
@@ -167,8 +665,75 @@ async fn init(
 
 fn main() {
     env_logger::init_from_env(env_logger::Env::new().default_filter_or("warn"));
+    let (input_devices_tx, _) = watch::channel(audio::InputDevices {
+        preferred: option_env!("INPUT_DEVICE").map(String::from),
+        fallback: option_env!("FALLBACK_INPUT_DEVICE").map(String::from),
+        channels: audio::Channels::Mix,
+        echo_reference: option_env!("ECHO_REFERENCE_DEVICE").map(String::from),
+        extra_mics: Vec::new(),
+    });
     tauri::Builder::default()
-        .invoke_handler(tauri::generate_handler![init])
+        .manage(InputDevices(input_devices_tx))
+        .manage(MicHealthStatus(
+            watch::channel(audio::MicHealth::default()).0,
+        ))
+        .manage(Statistics(watch::channel(Vec::new()).0))
+        .manage(Exposure(watch::channel(Vec::new()).0))
+        .setup(|app| {
+            let path = app
+                .path_resolver()
+                .app_data_dir()
+                .ok_or("Failed to resolve app data dir")?
+                .join("calibrations.json");
+            let (raw, _) = watch::channel(None);
+            app.manage(Calibration {
+                calibrations: Mutex::new(Calibrations::load(path)),
+                raw,
+            });
+            let dir = app
+                .path_resolver()
+                .app_data_dir()
+                .ok_or("Failed to resolve app data dir")?
+                .join("clips");
+            let store = ClipStore::load(dir);
+            let (settings, _) = watch::channel(store.settings());
+            app.manage(Clips {
+                store: Mutex::new(store),
+                settings,
+            });
+            let path = app
+                .path_resolver()
+                .app_data_dir()
+                .ok_or("Failed to resolve app data dir")?
+                .join("output_device.json");
+            let output_device = OutputDevice::load(path);
+            app.manage(audio::Playback::new(output_device.name().map(String::from)));
+            app.manage(Mutex::new(output_device));
+            Ok(())
+        })
+        .invoke_handler(tauri::generate_handler![
+            init,
+            list_calibrations,
+            calibrate,
+            clear_calibration,
+            list_input_devices,
+            set_input_device,
+            set_fallback_input_device,
+            set_echo_reference,
+            set_extra_mics,
+            set_input_channels,
+            mic_health,
+            statistics,
+            exposure,
+            list_clips,
+            delete_clip,
+            play_clip,
+            clip_settings,
+            set_clip_settings,
+            list_output_devices,
+            output_device,
+            set_output_device
+        ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
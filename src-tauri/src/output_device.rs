@@ -0,0 +1,33 @@
+use std::{fs, path::PathBuf};
+
+use anyhow::Context;
+
+use crate::json;
+
+/// The output device sounds play on by name, stored as JSON. `None` is the default output.
+pub struct OutputDevice {
+    path: PathBuf,
+    name: Option<String>,
+}
+
+impl OutputDevice {
+    /// Starts out on the default output if there's nothing stored at `path` yet
+    pub fn load(path: PathBuf) -> Self {
+        let name = json::load(&path, "output device");
+        Self { path, name }
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub fn set(&mut self, name: Option<String>) -> anyhow::Result<()> {
+        self.name = name;
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        fs::write(&self.path, serde_json::to_string(&self.name)?)
+            .with_context(|| format!("Failed to write output device {}", self.path.display()))
+    }
+}
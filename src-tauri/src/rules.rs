@@ -1,15 +1,12 @@
-use std::{
-    env,
-    sync::Arc,
-    time::{Duration, Instant},
-};
+use std::{env, sync::Arc, time::Duration};
 
 use rspotify::{clients::OAuthClient, AuthCodeSpotify};
 use tauri::AppHandle;
 use tokio::{sync::mpsc, time::sleep};
 
 use crate::{
-    audio::{self, PlayHandle},
+    audio::{PlayHandle, Playback, Side},
+    exposure::ExposureLimit,
     sound_files::SoundFiles,
     spotify,
     thresholds::Thresholds,
@@ -18,11 +15,13 @@ use crate::{
 pub struct RuleExecutor {
     spotify: AuthCodeSpotify,
     sound_files: SoundFiles,
+    playback: Playback,
+    /// Starting a sound through this stops the one before it
     play_handle_tx: mpsc::Sender<Option<PlayHandle>>,
 }
 
 impl RuleExecutor {
-    pub async fn new(app_handle: &AppHandle) -> anyhow::Result<Arc<Self>> {
+    pub async fn new(app_handle: &AppHandle, playback: Playback) -> anyhow::Result<Arc<Self>> {
         let sound_files = SoundFiles::resolve(&app_handle)?;
         let spotify = spotify::init().await?;
         let mut play_handle: Option<PlayHandle> = None;
@@ -36,13 +35,31 @@ impl RuleExecutor {
         Ok(Arc::new(Self {
             spotify,
             sound_files,
+            playback,
             play_handle_tx,
         }))
     }
 
-    pub async fn adjust_volume(self: Arc<Self>, thresholds: Thresholds) {
-        let volume_percent =
-            (110.0 + (thresholds.too_loud + thresholds.too_quiet) / 2.0).round() as u8;
+    /// `calibration_offset` turns dB SPL thresholds back into the dBFS this is tuned for,
+    /// `noise_floor` resolves thresholds relative to it
+    pub async fn adjust_volume(
+        self: Arc<Self>,
+        thresholds: Thresholds,
+        calibration_offset: Option<f32>,
+        noise_floor: Option<f32>,
+    ) {
+        let offset = match calibration_offset {
+            Some(offset) if !thresholds.metric.is_lufs() => offset,
+            _ => 0.0,
+        };
+        let (Some(too_loud), Some(too_quiet)) = (
+            thresholds.too_loud_level(noise_floor),
+            thresholds.too_quiet_level(noise_floor),
+        ) else {
+            log::info!("Noise floor not known yet, not adjusting volume");
+            return;
+        };
+        let volume_percent = (110.0 + (too_loud + too_quiet) / 2.0 - offset).round() as u8;
         if let Err(e) = self.spotify.volume(volume_percent.min(100), None).await {
             log::error!(
                 "{:?}",
@@ -53,7 +70,10 @@ impl RuleExecutor {
 
     pub async fn louder(self: Arc<Self>) {
         if let Err::<(), anyhow::Error>(e) = try {
-            let play_handle = audio::play_file(&self.sound_files.random_louder_announcement())?;
+            let play_handle = self
+                .playback
+                .play(self.sound_files.random_louder_announcement())
+                .await?;
             self.play_handle_tx.send(Some(play_handle)).await?;
         } {
             log::error!("{:?}", e.context("Announce louder failed"));
@@ -62,23 +82,30 @@ impl RuleExecutor {
 
     pub async fn quieter(self: Arc<Self>) {
         if let Err::<(), anyhow::Error>(e) = try {
-            let play_handle = audio::play_file(&self.sound_files.random_quieter_announcement())?;
+            let play_handle = self
+                .playback
+                .play(self.sound_files.random_quieter_announcement())
+                .await?;
             self.play_handle_tx.send(Some(play_handle)).await?;
         } {
             log::error!("{:?}", e.context("Announce quieter failed"));
         }
     }
 
-    pub async fn too_loud(self: Arc<Self>) {
-        log::info!("Too loud");
+    /// `side` is where the noise comes from, when it's known
+    pub async fn too_loud(self: Arc<Self>, side: Option<Side>) {
+        log::info!("Too loud, side {side:?}");
         if let Err::<(), anyhow::Error>(e) = try {
-            let play_handle = audio::play_file(&self.sound_files.too_loud_anouncement)?;
-            let expect_done_at = play_handle.expect_done_at();
+            let play_handle = self
+                .playback
+                .play(self.sound_files.too_loud_announcement(side))
+                .await?;
+            let completion = play_handle.completion();
             self.play_handle_tx.send(Some(play_handle)).await?;
-            sleep(expect_done_at - Instant::now()).await;
+            completion.wait().await;
 
             loop {
-                let play_handle = audio::play_file(&self.sound_files.annoying)?;
+                let play_handle = self.playback.play(&self.sound_files.annoying).await?;
                 self.play_handle_tx.send(Some(play_handle)).await?;
                 flicker_once().await?;
                 self.play_handle_tx.send(None).await?;
@@ -91,13 +118,61 @@ impl RuleExecutor {
         };
     }
 
+    /// A sudden bang, handled independently of the sustained loudness state
+    pub async fn impulse(self: Arc<Self>) {
+        log::info!("Impulse");
+        if let Err(e) = flicker_once().await {
+            log::error!("{:?}", e.context("Impulse failed"));
+        }
+    }
+
+    /// Part of the spectrum stayed above its limit, see [`crate::thresholds::BandLimit`]
+    pub async fn band_too_loud(self: Arc<Self>, name: String) {
+        log::info!("Too loud in band {name}");
+        if let Err::<(), anyhow::Error>(e) = try {
+            let play_handle = self
+                .playback
+                .play(self.sound_files.random_quieter_announcement())
+                .await?;
+            self.play_handle_tx.send(Some(play_handle)).await?;
+            flicker_once().await?;
+        } {
+            log::error!("{:?}", e.context(format!("Band {name} too loud failed")));
+        }
+    }
+
+    /// The day's noise dose reached the limit, handled independently of the loudness state
+    pub async fn exposure_limit(self: Arc<Self>, limit: ExposureLimit) {
+        log::info!(
+            "Reached {}% {:?} noise dose",
+            limit.dose_percent,
+            limit.model
+        );
+        if let Err::<(), anyhow::Error>(e) = try {
+            let play_handle = self
+                .playback
+                .play(self.sound_files.exposure_announcement())
+                .await?;
+            // Mixed over whatever else is playing instead of cutting it off
+            let completion = play_handle.completion();
+            flicker_once().await?;
+            completion.wait().await;
+            drop(play_handle);
+        } {
+            log::error!("{:?}", e.context("Exposure limit failed"));
+        }
+    }
+
     pub async fn acceptable(self: Arc<Self>) {
         log::info!("Acceptable");
         if let Err::<(), anyhow::Error>(e) = try {
-            let play_handle = audio::play_file(&self.sound_files.back_to_normal_announcement)?;
-            let expect_done_at = play_handle.expect_done_at();
+            let play_handle = self
+                .playback
+                .play(&self.sound_files.back_to_normal_announcement)
+                .await?;
+            let completion = play_handle.completion();
             self.play_handle_tx.send(Some(play_handle)).await?;
-            sleep(expect_done_at - Instant::now()).await;
+            completion.wait().await;
 
             let (a, b, c) = tokio::join!(
                 async move {
@@ -129,10 +204,13 @@ impl RuleExecutor {
     pub async fn too_quiet(self: Arc<Self>) {
         log::info!("Too quiet");
         if let Err::<(), anyhow::Error>(e) = try {
-            let play_handle = audio::play_file(&self.sound_files.too_quiet_anouncement)?;
-            let expect_done_at = play_handle.expect_done_at();
+            let play_handle = self
+                .playback
+                .play(&self.sound_files.too_quiet_anouncement)
+                .await?;
+            let completion = play_handle.completion();
             self.play_handle_tx.send(Some(play_handle)).await?;
-            sleep(expect_done_at - Instant::now()).await;
+            completion.wait().await;
 
             let (a, b) = tokio::join!(nice_lights_off(), async move {
                 if let Err(e) = self.spotify.pause_playback(None).await {
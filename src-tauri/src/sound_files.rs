@@ -3,16 +3,38 @@ use std::path::PathBuf;
 
 use tauri::AppHandle;
 
+use crate::audio::Side;
+
 pub struct SoundFiles {
     pub annoying: PathBuf,
     pub too_loud_anouncement: PathBuf,
+    /// Played instead when the noise comes from the left, e.g. naming that side of the room
+    pub too_loud_left_announcement: Option<PathBuf>,
+    pub too_loud_right_announcement: Option<PathBuf>,
     pub too_quiet_anouncement: PathBuf,
+    /// Played when the noise dose limit is reached, a quieter announcement when missing
+    pub exposure_announcement: Option<PathBuf>,
     pub back_to_normal_announcement: PathBuf,
     pub louder_anouncements: Vec<PathBuf>,
     pub quieter_anouncements: Vec<PathBuf>,
 }
 
 impl SoundFiles {
+    pub fn too_loud_announcement(&self, side: Option<Side>) -> &PathBuf {
+        match side {
+            Some(Side::Left) => self.too_loud_left_announcement.as_ref(),
+            Some(Side::Right) => self.too_loud_right_announcement.as_ref(),
+            Some(Side::Center) | None => None,
+        }
+        .unwrap_or(&self.too_loud_anouncement)
+    }
+
+    pub fn exposure_announcement(&self) -> &PathBuf {
+        self.exposure_announcement
+            .as_ref()
+            .unwrap_or_else(|| self.random_quieter_announcement())
+    }
+
     pub fn random_louder_announcement(&self) -> &PathBuf {
         self.louder_anouncements
             .choose(&mut rand::thread_rng())
@@ -35,6 +57,26 @@ impl SoundFiles {
                 .path_resolver()
                 .resolve_resource(env!("TOO_LOUD_ANNOUNCEMENT_FILE"))
                 .ok_or_else(|| anyhow::anyhow!("Failed to resolve too loud announcement file"))?,
+            too_loud_left_announcement: option_env!("TOO_LOUD_LEFT_ANNOUNCEMENT_FILE")
+                .map(|name| {
+                    app_handle
+                        .path_resolver()
+                        .resolve_resource(name)
+                        .ok_or_else(|| {
+                            anyhow::anyhow!("Failed to resolve too loud left announcement file")
+                        })
+                })
+                .transpose()?,
+            too_loud_right_announcement: option_env!("TOO_LOUD_RIGHT_ANNOUNCEMENT_FILE")
+                .map(|name| {
+                    app_handle
+                        .path_resolver()
+                        .resolve_resource(name)
+                        .ok_or_else(|| {
+                            anyhow::anyhow!("Failed to resolve too loud right announcement file")
+                        })
+                })
+                .transpose()?,
             back_to_normal_announcement: app_handle
                 .path_resolver()
                 .resolve_resource(env!("BACK_TO_NORMAL_ANNOUNCEMENT_FILE"))
@@ -43,6 +85,16 @@ impl SoundFiles {
                 .path_resolver()
                 .resolve_resource(env!("TOO_QUIET_ANNOUNCEMENT_FILE"))
                 .ok_or_else(|| anyhow::anyhow!("Failed to resolve too quiet announcement file"))?,
+            exposure_announcement: option_env!("EXPOSURE_ANNOUNCEMENT_FILE")
+                .map(|name| {
+                    app_handle
+                        .path_resolver()
+                        .resolve_resource(name)
+                        .ok_or_else(|| {
+                            anyhow::anyhow!("Failed to resolve exposure announcement file")
+                        })
+                })
+                .transpose()?,
             louder_anouncements: env!("LOUDER_ANNOUNCEMENT_FILES")
                 .split(",")
                 .map(|name| {
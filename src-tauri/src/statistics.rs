@@ -0,0 +1,294 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::audio::LevelHistogram;
+
+/// Levels are sampled this often, like a sound level meter logging statistics
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(100);
+/// Before this long was measured, a window's statistics aren't known yet
+const MIN_MEASURED: Duration = Duration::from_secs(10);
+
+/// How far back statistics look
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Window {
+    #[default]
+    OneMinute,
+    FifteenMinutes,
+    OneHour,
+}
+
+impl Window {
+    pub const ALL: [Window; 3] = [Window::OneMinute, Window::FifteenMinutes, Window::OneHour];
+
+    pub fn duration(self) -> Duration {
+        match self {
+            Window::OneMinute => Duration::from_mins(1),
+            Window::FifteenMinutes => Duration::from_mins(15),
+            Window::OneHour => Duration::from_hours(1),
+        }
+    }
+
+    fn samples(self) -> usize {
+        (self.duration().as_millis() / SAMPLE_INTERVAL.as_millis()) as usize
+    }
+}
+
+/// The levels noise ordinances and venue contracts are written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum StatisticalLevel {
+    /// Exceeded 10% of the time, the peaks of the noise
+    L10,
+    /// The median
+    L50,
+    /// Exceeded 90% of the time, the background
+    L90,
+    Lmax,
+    Lmin,
+    /// Equivalent continuous level over the whole window
+    Leq,
+}
+
+/// One statistic over one window, e.g. L90 over 15 minutes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Statistic {
+    pub level: StatisticalLevel,
+    pub window: Window,
+}
+
+/// Percentiles and extremes are to within 0.1 dB
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct WindowLevels {
+    pub window: Window,
+    /// How much of the window has been measured so far, in seconds
+    pub measured_seconds: f32,
+    pub l10: f32,
+    pub l50: f32,
+    pub l90: f32,
+    pub lmax: f32,
+    pub lmin: f32,
+    pub leq: f32,
+}
+
+impl WindowLevels {
+    pub fn get(&self, level: StatisticalLevel) -> f32 {
+        match level {
+            StatisticalLevel::L10 => self.l10,
+            StatisticalLevel::L50 => self.l50,
+            StatisticalLevel::L90 => self.l90,
+            StatisticalLevel::Lmax => self.lmax,
+            StatisticalLevel::Lmin => self.lmin,
+            StatisticalLevel::Leq => self.leq,
+        }
+    }
+}
+
+struct WindowTracker {
+    window: Window,
+    histogram: LevelHistogram,
+    /// Sum of the mean squares of the sampled levels, for the Leq
+    energy: f64,
+}
+
+/// Rolling statistics of a level sampled every [`SAMPLE_INTERVAL`], for every [`Window`]
+pub struct LevelStatistics {
+    /// Oldest first, as many as the longest window holds
+    samples: VecDeque<f32>,
+    trackers: Vec<WindowTracker>,
+    next_sample_at: Option<Instant>,
+}
+
+impl Default for LevelStatistics {
+    fn default() -> Self {
+        Self {
+            samples: VecDeque::new(),
+            trackers: Window::ALL
+                .into_iter()
+                .map(|window| WindowTracker {
+                    window,
+                    histogram: LevelHistogram::default(),
+                    energy: 0.0,
+                })
+                .collect(),
+            next_sample_at: None,
+        }
+    }
+}
+
+impl LevelStatistics {
+    /// Feeds the latest level, which is only sampled when it's time to.
+    /// Returns true when it was.
+    pub fn push(&mut self, level: f32, now: Instant) -> bool {
+        if self.next_sample_at.is_some_and(|next| now < next) {
+            return false;
+        }
+        self.next_sample_at = Some(now + SAMPLE_INTERVAL);
+
+        self.samples.push_back(level);
+        for tracker in &mut self.trackers {
+            tracker.histogram.add(level);
+            tracker.energy += mean_square(level);
+            let capacity = tracker.window.samples();
+            if self.samples.len() > capacity {
+                let leaving = self.samples[self.samples.len() - 1 - capacity];
+                tracker.histogram.remove(leaving);
+                tracker.energy = (tracker.energy - mean_square(leaving)).max(0.0);
+            }
+        }
+        let longest = Window::ALL
+            .map(Window::samples)
+            .into_iter()
+            .max()
+            .unwrap_or(0);
+        if self.samples.len() > longest {
+            self.samples.pop_front();
+        }
+        true
+    }
+
+    /// Starts over, e.g. when the level changes meaning
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Of the windows measured long enough
+    pub fn levels(&self) -> Vec<WindowLevels> {
+        self.trackers
+            .iter()
+            .filter_map(Self::window_levels)
+            .collect()
+    }
+
+    /// `None` while the window hasn't been measured long enough
+    pub fn get(&self, statistic: Statistic) -> Option<f32> {
+        self.trackers
+            .iter()
+            .find(|tracker| tracker.window == statistic.window)
+            .and_then(Self::window_levels)
+            .map(|levels| levels.get(statistic.level))
+    }
+
+    fn window_levels(tracker: &WindowTracker) -> Option<WindowLevels> {
+        let samples = tracker.histogram.len();
+        let measured = SAMPLE_INTERVAL * samples as u32;
+        if measured < MIN_MEASURED {
+            return None;
+        }
+        let percentile = |share| tracker.histogram.percentile(share);
+        Some(WindowLevels {
+            window: tracker.window,
+            measured_seconds: measured.as_secs_f32(),
+            l10: percentile(0.9)?,
+            l50: percentile(0.5)?,
+            l90: percentile(0.1)?,
+            lmax: percentile(1.0)?,
+            lmin: percentile(0.0)?,
+            leq: (10.0 * (tracker.energy / samples as f64).log10()) as f32,
+        })
+    }
+}
+
+fn mean_square(level: f32) -> f64 {
+    10f64.powf(f64::from(level) / 10.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pushes `levels` one sample interval apart, continuing from sample `start`
+    fn push_all(statistics: &mut LevelStatistics, start: Instant, first: u32, levels: &[f32]) {
+        for (i, &level) in (first..).zip(levels) {
+            assert!(statistics.push(level, start + SAMPLE_INTERVAL * i));
+        }
+    }
+
+    fn one_minute(statistics: &LevelStatistics) -> WindowLevels {
+        statistics
+            .levels()
+            .into_iter()
+            .find(|levels| levels.window == Window::OneMinute)
+            .unwrap()
+    }
+
+    fn assert_near(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 0.01, "{actual}, not {expected}");
+    }
+
+    #[test]
+    fn percentiles_of_a_known_distribution() {
+        // Every level from 40 to 139 dB once, shuffled. Centered in the histogram's bins.
+        let levels = (0..100)
+            .map(|i| 40.05 + ((i * 37) % 100) as f32)
+            .collect::<Vec<_>>();
+        let mut statistics = LevelStatistics::default();
+        push_all(&mut statistics, Instant::now(), 0, &levels);
+        let levels = one_minute(&statistics);
+        assert_near(levels.measured_seconds, 10.0);
+        assert_near(levels.l10, 129.05);
+        assert_near(levels.l50, 89.05);
+        assert_near(levels.l90, 49.05);
+        assert_near(levels.lmax, 139.05);
+        assert_near(levels.lmin, 40.05);
+    }
+
+    #[test]
+    fn leq_averages_the_energy() {
+        let levels = [60.0, 70.0].repeat(50);
+        let mut statistics = LevelStatistics::default();
+        push_all(&mut statistics, Instant::now(), 0, &levels);
+        assert_near(one_minute(&statistics).leq, 67.40);
+    }
+
+    #[test]
+    fn unknown_until_measured_long_enough() {
+        let mut statistics = LevelStatistics::default();
+        let start = Instant::now();
+        push_all(&mut statistics, start, 0, &[60.0; 99]);
+        assert!(statistics.levels().is_empty());
+        let statistic = Statistic {
+            level: StatisticalLevel::L90,
+            window: Window::OneMinute,
+        };
+        assert_eq!(statistics.get(statistic), None);
+        push_all(&mut statistics, start, 99, &[60.0]);
+        assert!(statistics.get(statistic).is_some());
+    }
+
+    #[test]
+    fn samples_once_per_interval() {
+        let mut statistics = LevelStatistics::default();
+        let start = Instant::now();
+        assert!(statistics.push(60.0, start));
+        assert!(!statistics.push(90.0, start + SAMPLE_INTERVAL / 2));
+        assert!(statistics.push(60.0, start + SAMPLE_INTERVAL));
+    }
+
+    #[test]
+    fn windows_slide() {
+        let minute = Window::OneMinute.samples();
+        let mut statistics = LevelStatistics::default();
+        let start = Instant::now();
+        push_all(&mut statistics, start, 0, &vec![80.05; minute]);
+        push_all(&mut statistics, start, minute as u32, &vec![50.05; minute]);
+        let levels = one_minute(&statistics);
+        assert_near(levels.l10, 50.05);
+        assert_near(levels.leq, 50.05);
+        let statistic = |level| Statistic {
+            level,
+            window: Window::FifteenMinutes,
+        };
+        // Still remembers the loud minute
+        assert_near(
+            statistics.get(statistic(StatisticalLevel::L10)).unwrap(),
+            80.05,
+        );
+        assert_near(
+            statistics.get(statistic(StatisticalLevel::L90)).unwrap(),
+            50.05,
+        );
+    }
+}
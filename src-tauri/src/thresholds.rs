@@ -1,26 +1,143 @@
+use std::time::{Duration, Instant};
+
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+use crate::{
+    audio::{Metric, Spectrum},
+    exposure::ExposureLimit,
+    statistics::Statistic,
+};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Thresholds {
     pub too_loud: f32,
     pub too_quiet: f32,
     pub grace: f32,
+    /// Whether `too_loud` is a level or dB above the noise floor
+    #[serde(default)]
+    pub too_loud_baseline: Baseline,
+    #[serde(default)]
+    pub too_quiet_baseline: Baseline,
+    /// What the limits are expressed in: dBFS, dB SPL once the microphone is calibrated, or LUFS
+    #[serde(default)]
+    pub metric: Metric,
+    /// Compare the limits against a statistic of `metric` over a window instead of its
+    /// current value, e.g. the L10 over 15 minutes a noise ordinance is written in
+    #[serde(default)]
+    pub statistic: Option<Statistic>,
+    /// Warn once the day's noise dose reaches this, whatever the current level
+    #[serde(default)]
+    pub exposure_limit: Option<ExposureLimit>,
+    /// Checked independently of the broadband limits above
+    #[serde(default)]
+    pub band_limits: Vec<BandLimit>,
+}
+
+/// What a limit is measured from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Baseline {
+    #[default]
+    Absolute,
+    /// The limit is in dB above the ambient noise floor, so the same limits
+    /// work in a quiet flat and a noisy bar
+    NoiseFloor,
+}
+
+impl Baseline {
+    /// `None` while the noise floor isn't known yet
+    fn resolve(self, limit: f32, noise_floor: Option<f32>) -> Option<f32> {
+        match self {
+            Baseline::Absolute => Some(limit),
+            Baseline::NoiseFloor => noise_floor.map(|floor| floor + limit),
+        }
+    }
 }
 
 impl Thresholds {
-    pub fn too_loud(&self, loudness: f32) -> bool {
-        loudness > self.too_loud
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let relative =
+            [self.too_loud_baseline, self.too_quiet_baseline].contains(&Baseline::NoiseFloor);
+        anyhow::ensure!(
+            !relative || self.metric.has_noise_floor(),
+            "Limits above the noise floor can't be used with {:?}",
+            self.metric
+        );
+        Ok(())
+    }
+
+    /// Limits relative to a noise floor that isn't known yet are never crossed
+    pub fn too_loud_level(&self, noise_floor: Option<f32>) -> Option<f32> {
+        self.too_loud_baseline.resolve(self.too_loud, noise_floor)
+    }
+
+    pub fn too_quiet_level(&self, noise_floor: Option<f32>) -> Option<f32> {
+        self.too_quiet_baseline.resolve(self.too_quiet, noise_floor)
+    }
+
+    pub fn too_loud(&self, loudness: f32, noise_floor: Option<f32>) -> bool {
+        self.too_loud_level(noise_floor)
+            .is_some_and(|too_loud| loudness > too_loud)
+    }
+
+    pub fn too_quiet(&self, loudness: f32, noise_floor: Option<f32>) -> bool {
+        self.too_quiet_level(noise_floor)
+            .is_some_and(|too_quiet| loudness < too_quiet)
     }
 
-    pub fn too_quiet(&self, loudness: f32) -> bool {
-        loudness < self.too_quiet
+    pub fn acceptable_from_too_loud(&self, loudness: f32, noise_floor: Option<f32>) -> bool {
+        self.too_loud_level(noise_floor)
+            .is_some_and(|too_loud| loudness < too_loud - self.grace)
     }
 
-    pub fn acceptable_from_too_loud(&self, loudness: f32) -> bool {
-        loudness < self.too_loud - self.grace
+    pub fn acceptable_from_too_quiet(&self, loudness: f32, noise_floor: Option<f32>) -> bool {
+        self.too_quiet_level(noise_floor)
+            .is_some_and(|too_quiet| loudness > too_quiet + self.grace)
+    }
+}
+
+/// A limit on part of the spectrum, e.g. bass that carries through the floor
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BandLimit {
+    pub name: String,
+    pub low_hz: f32,
+    pub high_hz: f32,
+    /// Unweighted dBFS, or dB SPL once the microphone is calibrated
+    pub too_loud: f32,
+    /// How long the band has to stay above `too_loud` before it counts
+    pub seconds: f32,
+}
+
+impl BandLimit {
+    pub fn level(&self, spectrum: &Spectrum) -> f32 {
+        spectrum.level_between(self.low_hz, self.high_hz)
     }
+}
+
+/// Whether a [`BandLimit`] is breached, uses the thresholds' `grace` to recover
+#[derive(Debug, Default)]
+pub struct BandLimitState {
+    above_since: Option<Instant>,
+    pub breached: bool,
+}
 
-    pub fn acceptable_from_too_quiet(&self, loudness: f32) -> bool {
-        loudness > self.too_quiet + self.grace
+impl BandLimitState {
+    /// Returns true when `breached` changed
+    pub fn update(&mut self, limit: &BandLimit, grace: f32, level: f32, now: Instant) -> bool {
+        if level > limit.too_loud {
+            let above_since = *self.above_since.get_or_insert(now);
+            if !self.breached
+                && now - above_since >= Duration::from_secs_f32(limit.seconds.max(0.0))
+            {
+                self.breached = true;
+                return true;
+            }
+        } else {
+            self.above_since = None;
+            if self.breached && level < limit.too_loud - grace {
+                self.breached = false;
+                return true;
+            }
+        }
+        false
     }
 }
@@ -1,24 +1,141 @@
 import "@picocss/pico/css/pico.min.css";
-import { emit } from "@tauri-apps/api/event";
+import { emit, listen } from "@tauri-apps/api/event";
 import { invoke } from "@tauri-apps/api/tauri";
-import { createEffect, createSignal, onMount } from "solid-js";
+import {
+  createEffect,
+  createSignal,
+  For,
+  onCleanup,
+  onMount,
+  Show,
+} from "solid-js";
+import Calibration from "./Calibration";
+import Clips from "./Clips";
+import { ExposureLimit, ExposureModel, modelNames } from "./exposure";
+import Mics, { MicSettings } from "./Mics";
+import { describeMicStatus, MicStatus } from "./micStatus";
+import {
+  Statistic,
+  StatisticalLevel,
+  StatisticsWindow,
+  windowNames,
+} from "./statistics";
+
+type InputDeviceInfo = {
+  name: string;
+  is_default: boolean;
+  configs: { channels: number }[];
+};
+
+type OutputDeviceInfo = {
+  name: string;
+  is_default: boolean;
+};
+
+type BandLimit = {
+  name: string;
+  low_hz: number;
+  high_hz: number;
+  too_loud: number;
+  seconds: number;
+};
+
+type FilterStage = {
+  mode: "LowPass" | "BandPass" | "HighPass" | "Notch";
+  cutoff: number;
+  resonance: number;
+};
 
 function App() {
   const [thresholds, setThresholds] = createSignal({
     too_loud: -25.0,
     too_quiet: -60.0,
     grace: 6.0,
+    metric: "Leq",
+    too_loud_baseline: "Absolute",
+    too_quiet_baseline: "Absolute",
+    statistic: null as Statistic | null,
+    exposure_limit: null as ExposureLimit | null,
+    band_limits: [] as BandLimit[],
+  });
+  // The noise floor is tracked in the weighted level, not in LUFS or single bands
+  const hasNoiseFloor = (metric: string) =>
+    !["MomentaryLufs", "ShortTermLufs", "Bass", "Voice"].includes(metric);
+  const setBandLimit = (index: number, changes: Partial<BandLimit>) =>
+    setThresholds((current) => ({
+      ...current,
+      band_limits: current.band_limits.map((limit, i) =>
+        i === index ? { ...limit, ...changes } : limit
+      ),
+    }));
+  const [meterSettings, setMeterSettings] = createSignal({
+    rms_seconds: 5,
+    weighting: "A",
+    impulse_level: -3.0,
+    bands: "ThirdOctave",
+    aggregation: "Max",
+    mic_weights: [] as number[],
+    mic_spacing: 0.2,
+    filters: [] as FilterStage[],
+    extra_mics: [] as MicSettings[],
   });
-  const [rmsSeconds, setRmsSeconds] = createSignal(5);
+  const setFilter = (index: number, changes: Partial<FilterStage>) =>
+    setMeterSettings((current) => ({
+      ...current,
+      filters: current.filters.map((stage, i) =>
+        i === index ? { ...stage, ...changes } : stage
+      ),
+    }));
+  const [inputDevices, setInputDevices] = createSignal<InputDeviceInfo[]>([]);
+  const [inputDevice, setInputDevice] = createSignal<string | null>(null);
+  // "Mix" or the index of the only channel that's measured
+  const [inputChannel, setInputChannel] = createSignal("Mix");
+  const selectInputChannel = (channel: string) => {
+    setInputChannel(channel);
+    invoke("set_input_channels", {
+      channels: channel === "Mix" ? "Mix" : { Select: Number(channel) },
+    });
+  };
+  const inputChannelCount = () => {
+    const device = inputDevices().find((device) =>
+      inputDevice() === null ? device.is_default : device.name === inputDevice()
+    );
+    return Math.max(0, ...(device?.configs ?? []).map((c) => c.channels));
+  };
+  const [outputDevices, setOutputDevices] = createSignal<OutputDeviceInfo[]>(
+    []
+  );
+  const [outputDevice, setOutputDevice] = createSignal<string | null>(null);
+  const outputDeviceMissing = () => {
+    const name = outputDevice();
+    return (
+      name !== null && !outputDevices().some((device) => device.name === name)
+    );
+  };
+  const [micStatus, setMicStatus] = createSignal<MicStatus>({
+    status: "Connecting",
+  });
+  const unlisten: (() => void)[] = [];
 
   onMount(async () => {
+    unlisten.push(
+      await listen<MicStatus>("mic-status", (event) => {
+        setMicStatus(event.payload);
+      })
+    );
     invoke("init", {
-      initialRmsSeconds: rmsSeconds(),
+      initialMeterSettings: meterSettings(),
       initialThresholds: thresholds(),
     });
+    setInputDevices(await invoke<InputDeviceInfo[]>("list_input_devices"));
+    setOutputDevices(await invoke<OutputDeviceInfo[]>("list_output_devices"));
+    setOutputDevice(await invoke<string | null>("output_device"));
+  });
+  onCleanup(() => {
+    unlisten.forEach((fn) => fn());
   });
   createEffect(() => {
-    emit("rms-seconds", { rms_seconds: rmsSeconds() });
+    emit("meter-settings", meterSettings());
   });
   createEffect(() => {
     emit("thresholds", thresholds());
@@ -27,6 +144,7 @@ function App() {
   return (
     <main class="container">
       <h1 style="margin-top: 1rem;">Decibender Admin!</h1>
+      <p>Mic: {describeMicStatus(micStatus())}</p>
       <div class="grid">
         <label>
           Too Quiet:
@@ -36,9 +154,8 @@ function App() {
             value={thresholds().too_quiet}
             onChange={(e) =>
               setThresholds((current) => ({
-                too_loud: current.too_loud,
+                ...current,
                 too_quiet: Number(e.target.value),
-                grace: current.grace,
               }))
             }
             step={0.5}
@@ -46,6 +163,27 @@ function App() {
             max={0}
           />
         </label>
+        <label>
+          Too Quiet Is:
+          <select
+            name="tooQuietBaseline"
+            value={thresholds().too_quiet_baseline}
+            onChange={(e) =>
+              setThresholds((current) => ({
+                ...current,
+                too_quiet_baseline: e.target.value,
+              }))
+            }
+          >
+            <option value="Absolute">A level</option>
+            <option
+              value="NoiseFloor"
+              disabled={!hasNoiseFloor(thresholds().metric)}
+            >
+              dB above the noise floor
+            </option>
+          </select>
+        </label>
         <label>
           Too Loud:
           <input
@@ -54,9 +192,8 @@ function App() {
             value={thresholds().too_loud}
             onChange={(e) =>
               setThresholds((current) => ({
+                ...current,
                 too_loud: Number(e.target.value),
-                too_quiet: current.too_quiet,
-                grace: current.grace,
               }))
             }
             step={0.5}
@@ -64,6 +201,27 @@ function App() {
             max={100}
           />
         </label>
+        <label>
+          Too Loud Is:
+          <select
+            name="tooLoudBaseline"
+            value={thresholds().too_loud_baseline}
+            onChange={(e) =>
+              setThresholds((current) => ({
+                ...current,
+                too_loud_baseline: e.target.value,
+              }))
+            }
+          >
+            <option value="Absolute">A level</option>
+            <option
+              value="NoiseFloor"
+              disabled={!hasNoiseFloor(thresholds().metric)}
+            >
+              dB above the noise floor
+            </option>
+          </select>
+        </label>
       </div>
       <div class="grid">
         <label>
@@ -74,8 +232,7 @@ function App() {
             value={thresholds().grace}
             onChange={(e) =>
               setThresholds((current) => ({
-                too_loud: current.too_loud,
-                too_quiet: current.too_quiet,
+                ...current,
                 grace: Number(e.target.value),
               }))
             }
@@ -85,26 +242,515 @@ function App() {
           />
         </label>
         <label>
-          RMS Seconds:
+          Leq Seconds:
           <input
             type="number"
             name="rmsSeconds"
-            value={rmsSeconds()}
-            onChange={(e) => setRmsSeconds(Number(e.target.value))}
+            value={meterSettings().rms_seconds}
+            onChange={(e) =>
+              setMeterSettings((current) => ({
+                ...current,
+                rms_seconds: Number(e.target.value),
+              }))
+            }
             step={0.5}
             min={0.5}
             max={10}
           />
         </label>
+        <label>
+          Impulse Level (dBTP):
+          <input
+            type="number"
+            name="impulseLevel"
+            value={meterSettings().impulse_level}
+            onChange={(e) =>
+              setMeterSettings((current) => ({
+                ...current,
+                impulse_level: Number(e.target.value),
+              }))
+            }
+            step={0.5}
+            min={-40}
+            max={0}
+          />
+        </label>
+        <label>
+          Stereo Mic Spacing (m):
+          <input
+            type="number"
+            name="micSpacing"
+            value={meterSettings().mic_spacing}
+            onChange={(e) =>
+              setMeterSettings((current) => ({
+                ...current,
+                mic_spacing: Number(e.target.value),
+              }))
+            }
+            step={0.01}
+            min={0.01}
+          />
+        </label>
+        <label>
+          Metric:
+          <select
+            name="metric"
+            value={thresholds().metric}
+            onChange={(e) =>
+              setThresholds((current) => {
+                const metric = e.target.value;
+                const baseline = (baseline: string) =>
+                  hasNoiseFloor(metric) ? baseline : "Absolute";
+                return {
+                  ...current,
+                  metric,
+                  too_loud_baseline: baseline(current.too_loud_baseline),
+                  too_quiet_baseline: baseline(current.too_quiet_baseline),
+                };
+              })
+            }
+          >
+            <option value="Leq">Leq over Leq Seconds</option>
+            <option value="Fast">Fast (125 ms)</option>
+            <option value="Slow">Slow (1 s)</option>
+            <option value="Impulse">Impulse</option>
+            <option value="MomentaryLufs">Momentary (LUFS)</option>
+            <option value="ShortTermLufs">Short-term (LUFS)</option>
+            <option value="Bass">Bass (20-160 Hz)</option>
+            <option value="Voice">Voice (250 Hz-3.2 kHz)</option>
+            <option value="Crowd">Crowd (Leq without echo)</option>
+            <option value="Speech">Speech (Leq of speech only)</option>
+          </select>
+        </label>
+        <label>
+          Compared As:
+          <select
+            name="statistic"
+            value={thresholds().statistic?.level ?? ""}
+            onChange={(e) =>
+              setThresholds((current) => ({
+                ...current,
+                statistic:
+                  e.target.value === ""
+                    ? null
+                    : {
+                        window: current.statistic?.window ?? "OneMinute",
+                        level: e.target.value as StatisticalLevel,
+                      },
+              }))
+            }
+          >
+            <option value="">Current value</option>
+            <option value="L10">L10 (exceeded 10% of the time)</option>
+            <option value="L50">L50 (median)</option>
+            <option value="L90">L90 (background)</option>
+            <option value="Lmax">Lmax</option>
+            <option value="Lmin">Lmin</option>
+            <option value="Leq">Leq</option>
+          </select>
+        </label>
+        <Show when={thresholds().statistic}>
+          {(statistic) => (
+            <label>
+              Over:
+              <select
+                name="statisticWindow"
+                value={statistic().window}
+                onChange={(e) =>
+                  setThresholds((current) => ({
+                    ...current,
+                    statistic: {
+                      ...statistic(),
+                      window: e.target.value as StatisticsWindow,
+                    },
+                  }))
+                }
+              >
+                <For each={Object.entries(windowNames)}>
+                  {([window, name]) => <option value={window}>{name}</option>}
+                </For>
+              </select>
+            </label>
+          )}
+        </Show>
+        <label>
+          Weighting:
+          <select
+            name="weighting"
+            value={meterSettings().weighting}
+            onChange={(e) =>
+              setMeterSettings((current) => ({
+                ...current,
+                weighting: e.target.value,
+              }))
+            }
+          >
+            <option value="A">A (dBA)</option>
+            <option value="C">C (dBC)</option>
+            <option value="Z">Z (flat)</option>
+          </select>
+        </label>
+        <label>
+          Spectrum:
+          <select
+            name="bands"
+            value={meterSettings().bands}
+            onChange={(e) =>
+              setMeterSettings((current) => ({
+                ...current,
+                bands: e.target.value,
+              }))
+            }
+          >
+            <option value="Octave">Octave bands</option>
+            <option value="ThirdOctave">Third-octave bands</option>
+          </select>
+        </label>
+      </div>
+      <For each={thresholds().band_limits}>
+        {(limit, index) => (
+          <div class="grid">
+            <label>
+              Band:
+              <input
+                type="text"
+                value={limit.name}
+                onChange={(e) =>
+                  setBandLimit(index(), { name: e.target.value })
+                }
+              />
+            </label>
+            <label>
+              From (Hz):
+              <input
+                type="number"
+                value={limit.low_hz}
+                onChange={(e) =>
+                  setBandLimit(index(), { low_hz: Number(e.target.value) })
+                }
+                min={20}
+                max={20000}
+              />
+            </label>
+            <label>
+              To (Hz):
+              <input
+                type="number"
+                value={limit.high_hz}
+                onChange={(e) =>
+                  setBandLimit(index(), { high_hz: Number(e.target.value) })
+                }
+                min={20}
+                max={20000}
+              />
+            </label>
+            <label>
+              Too Loud:
+              <input
+                type="number"
+                value={limit.too_loud}
+                onChange={(e) =>
+                  setBandLimit(index(), { too_loud: Number(e.target.value) })
+                }
+                step={0.5}
+                min={-100}
+                max={0}
+              />
+            </label>
+            <label>
+              For (s):
+              <input
+                type="number"
+                value={limit.seconds}
+                onChange={(e) =>
+                  setBandLimit(index(), { seconds: Number(e.target.value) })
+                }
+                step={1}
+                min={0}
+              />
+            </label>
+            <button
+              class="secondary"
+              onClick={() =>
+                setThresholds((current) => ({
+                  ...current,
+                  band_limits: current.band_limits.filter(
+                    (_, i) => i !== index()
+                  ),
+                }))
+              }
+            >
+              Remove
+            </button>
+          </div>
+        )}
+      </For>
+      <button
+        class="secondary"
+        onClick={() =>
+          setThresholds((current) => ({
+            ...current,
+            band_limits: [
+              ...current.band_limits,
+              {
+                name: "Bass",
+                low_hz: 20,
+                high_hz: 160,
+                too_loud: -40,
+                seconds: 10,
+              },
+            ],
+          }))
+        }
+      >
+        Add Band Limit
+      </button>
+      <div class="grid">
+        <label>
+          Noise Dose Warning:
+          <select
+            name="exposureModel"
+            value={thresholds().exposure_limit?.model ?? ""}
+            onChange={(e) =>
+              setThresholds((current) => ({
+                ...current,
+                exposure_limit:
+                  e.target.value === ""
+                    ? null
+                    : {
+                        dose_percent:
+                          current.exposure_limit?.dose_percent ?? 100,
+                        model: e.target.value as ExposureModel,
+                      },
+              }))
+            }
+          >
+            <option value="">Off</option>
+            <For each={Object.entries(modelNames)}>
+              {([model, name]) => <option value={model}>{name}</option>}
+            </For>
+          </select>
+        </label>
+        <Show when={thresholds().exposure_limit}>
+          {(limit) => (
+            <label>
+              At Dose (%):
+              <input
+                type="number"
+                name="exposureDose"
+                value={limit().dose_percent}
+                onChange={(e) =>
+                  setThresholds((current) => ({
+                    ...current,
+                    exposure_limit: {
+                      ...limit(),
+                      dose_percent: Number(e.target.value),
+                    },
+                  }))
+                }
+                step={5}
+                min={1}
+              />
+            </label>
+          )}
+        </Show>
+        <button class="secondary" onClick={() => emit("reset-exposure")}>
+          Reset Dose
+        </button>
+      </div>
+      <For each={meterSettings().filters}>
+        {(stage, index) => (
+          <div class="grid">
+            <label>
+              Filter:
+              <select
+                value={stage.mode}
+                onChange={(e) =>
+                  setFilter(index(), {
+                    mode: e.target.value as FilterStage["mode"],
+                  })
+                }
+              >
+                <option value="HighPass">High-pass</option>
+                <option value="LowPass">Low-pass</option>
+                <option value="BandPass">Band-pass</option>
+                <option value="Notch">Notch</option>
+              </select>
+            </label>
+            <label>
+              Cutoff (Hz):
+              <input
+                type="number"
+                value={stage.cutoff}
+                onChange={(e) =>
+                  setFilter(index(), { cutoff: Number(e.target.value) })
+                }
+                min={1}
+                max={24000}
+              />
+            </label>
+            <label>
+              Resonance:
+              <input
+                type="number"
+                value={stage.resonance}
+                onChange={(e) =>
+                  setFilter(index(), { resonance: Number(e.target.value) })
+                }
+                step={0.05}
+                min={0}
+                max={1}
+              />
+            </label>
+            <button
+              class="secondary"
+              onClick={() =>
+                setMeterSettings((current) => ({
+                  ...current,
+                  filters: current.filters.filter((_, i) => i !== index()),
+                }))
+              }
+            >
+              Remove
+            </button>
+          </div>
+        )}
+      </For>
+      <button
+        class="secondary"
+        onClick={() =>
+          setMeterSettings((current) => ({
+            ...current,
+            filters: [
+              ...current.filters,
+              { mode: "Notch", cutoff: 50, resonance: 0.9 },
+            ],
+          }))
+        }
+      >
+        Add Filter
+      </button>
+      <div class="grid">
+        <label>
+          Input Device:
+          <select
+            name="inputDevice"
+            onFocus={async () =>
+              setInputDevices(await invoke<InputDeviceInfo[]>("list_input_devices"))
+            }
+            onChange={(e) => {
+              setInputDevice(e.target.value || null);
+              invoke("set_input_device", { name: e.target.value || null });
+              // Channels of the previous device mean nothing on this one
+              selectInputChannel("Mix");
+            }}
+          >
+            <option value="">Default</option>
+            <For each={inputDevices()}>
+              {(device) => (
+                <option value={device.name}>
+                  {device.name}
+                  {device.is_default ? " (default)" : ""}
+                </option>
+              )}
+            </For>
+          </select>
+        </label>
+        <Show when={inputChannelCount() > 1}>
+          <label>
+            Input Channels:
+            <select
+              name="inputChannels"
+              value={inputChannel()}
+              onChange={(e) => selectInputChannel(e.target.value)}
+            >
+              <option value="Mix">Mix all</option>
+              <For
+                each={Array.from(
+                  { length: inputChannelCount() },
+                  (_, channel) => channel
+                )}
+              >
+                {(channel) => (
+                  <option value={String(channel)}>Channel {channel + 1}</option>
+                )}
+              </For>
+            </select>
+          </label>
+        </Show>
+        <label>
+          Fallback Input Device:
+          <select
+            name="fallbackInputDevice"
+            onChange={(e) =>
+              invoke("set_fallback_input_device", {
+                name: e.target.value || null,
+              })
+            }
+          >
+            <option value="">None</option>
+            <For each={inputDevices()}>
+              {(device) => <option value={device.name}>{device.name}</option>}
+            </For>
+          </select>
+        </label>
+        <label>
+          Echo Reference (Output Monitor):
+          <select
+            name="echoReference"
+            onChange={(e) =>
+              invoke("set_echo_reference", {
+                name: e.target.value || null,
+              })
+            }
+          >
+            <option value="">None</option>
+            <For each={inputDevices()}>
+              {(device) => <option value={device.name}>{device.name}</option>}
+            </For>
+          </select>
+        </label>
+        <label>
+          Announcement Output:
+          <select
+            name="outputDevice"
+            value={outputDevice() ?? ""}
+            onFocus={async () =>
+              setOutputDevices(
+                await invoke<OutputDeviceInfo[]>("list_output_devices")
+              )
+            }
+            onChange={async (e) => {
+              const name = e.target.value || null;
+              setOutputDevice(name);
+              await invoke("set_output_device", { name });
+            }}
+          >
+            <option value="">Default</option>
+            <For each={outputDevices()}>
+              {(device) => (
+                <option value={device.name}>
+                  {device.name}
+                  {device.is_default ? " (default)" : ""}
+                </option>
+              )}
+            </For>
+            <Show when={outputDeviceMissing()}>
+              <option value={outputDevice() ?? ""}>
+                {outputDevice()} (unavailable, using default)
+              </option>
+            </Show>
+          </select>
+        </label>
       </div>
       <div class="grid">
         <button
           onClick={async () => {
             await emit("louder");
             setThresholds((current) => ({
+              ...current,
               too_loud: current.too_loud + 3.0,
               too_quiet: current.too_quiet + 3.0,
-              grace: current.grace,
             }));
           }}
         >
@@ -114,15 +760,38 @@ function App() {
           onClick={async () => {
             await emit("quieter");
             setThresholds((current) => ({
+              ...current,
               too_loud: current.too_loud - 3.0,
               too_quiet: current.too_quiet - 3.0,
-              grace: current.grace,
             }));
           }}
         >
           Quieter!
         </button>
       </div>
+      <Mics
+        deviceNames={inputDevices().map((device) => device.name)}
+        aggregation={meterSettings().aggregation}
+        weights={meterSettings().mic_weights}
+        onAggregationChange={(aggregation) =>
+          setMeterSettings((current) => ({ ...current, aggregation }))
+        }
+        onWeightsChange={(mic_weights) =>
+          setMeterSettings((current) => ({ ...current, mic_weights }))
+        }
+        micSettings={meterSettings().extra_mics}
+        onMicSettingsChange={(extra_mics) =>
+          setMeterSettings((current) => ({ ...current, extra_mics }))
+        }
+      />
+      <Calibration
+        device={
+          micStatus().status === "Connected"
+            ? (micStatus() as { device: string }).device
+            : null
+        }
+      />
+      <Clips />
     </main>
   );
 }
@@ -1,23 +1,158 @@
 import "@picocss/pico/css/pico.min.css";
 import { listen } from "@tauri-apps/api/event";
-import { createSignal, onCleanup, onMount } from "solid-js";
+import { invoke } from "@tauri-apps/api/tauri";
+import { createSignal, For, onCleanup, onMount, Show } from "solid-js";
 import "./App.css";
+import {
+  describeMicProblem,
+  describeMicStatus,
+  MicHealth,
+  MicStatus,
+} from "./micStatus";
+import { describeTimeToFull, Dose, modelNames } from "./exposure";
+import {
+  Statistic,
+  statisticOf,
+  WindowLevels,
+  windowNames,
+  windowSeconds,
+} from "./statistics";
+
+type Band = {
+  center: number;
+  // Silence is -Infinity, which arrives as null
+  level: number | null;
+};
+
+const formatFrequency = (hz: number) =>
+  hz >= 1000 ? `${(hz / 1000).toPrecision(2)} kHz` : `${hz.toPrecision(2)} Hz`;
+
+// -90 is all the way left, 90 all the way right
+const describeBearing = (bearing: number) => {
+  const degrees = Math.abs(bearing).toFixed(0);
+  if (bearing < -20) return `left, ${degrees}°`;
+  if (bearing > 20) return `right, ${degrees}°`;
+  return `center, ${degrees}° ${bearing < 0 ? "left" : "right"}`;
+};
 
 function App() {
   const [thresholds, setThresholds] = createSignal({
     too_loud: -10.0,
     too_quiet: -90.0,
     grace: 6.0,
+    metric: "Leq",
+    too_loud_baseline: "Absolute",
+    too_quiet_baseline: "Absolute",
+    statistic: null as Statistic | null,
   });
   const [state, setState] = createSignal("Acceptable");
-  const [loudness, setLoudness] = createSignal(-50.0);
+  const [breachedBands, setBreachedBands] = createSignal<string[]>([]);
+  const [measurement, setMeasurement] = createSignal({
+    loudness: -50.0,
+    spl: false,
+    crowd: null as number | null,
+    voice_activity: {
+      activity: "Silence",
+      speech_loudness: -50.0 as number | null,
+      speech_ratio: 0.0,
+    },
+    noise_floor: null as number | null,
+    direction: null as { bearing: number; confidence: number } | null,
+    gated: false,
+    weighting: "A",
+    time_weighted: { fast: -50.0, slow: -50.0, impulse: -50.0 },
+    lufs: { momentary: -50.0, short_term: -50.0, integrated: -50.0 },
+  });
+  const [spectrum, setSpectrum] = createSignal<Band[]>([]);
+  const [statistics, setStatistics] = createSignal<WindowLevels[]>([]);
+  const [doses, setDoses] = createSignal<Dose[]>([]);
+  // What the limits are compared against, null while a statistic isn't known yet
+  const loudness = () => {
+    const statistic = thresholds().statistic;
+    if (statistic) {
+      const levels = statistics().find(
+        (levels) => levels.window === statistic.window
+      );
+      return levels ? statisticOf(levels, statistic.level) : null;
+    }
+    return currentLevel();
+  };
+  const currentLevel = () => {
+    switch (thresholds().metric) {
+      case "Fast":
+        return measurement().time_weighted.fast;
+      case "Slow":
+        return measurement().time_weighted.slow;
+      case "Impulse":
+        return measurement().time_weighted.impulse;
+      case "MomentaryLufs":
+        return measurement().lufs.momentary;
+      case "ShortTermLufs":
+        return measurement().lufs.short_term;
+      case "Bass":
+        return bandLevel(20, 160);
+      case "Voice":
+        return bandLevel(250, 3200);
+      case "Crowd":
+        return measurement().crowd ?? measurement().loudness;
+      case "Speech":
+        return measurement().voice_activity.speech_loudness;
+      default:
+        return measurement().loudness;
+    }
+  };
+  // Relative limits are drawn at the floor until it's known
+  const limit = (value: number, baseline: string) =>
+    baseline === "NoiseFloor"
+      ? (measurement().noise_floor ?? -Infinity) + value
+      : value;
+  const tooLoud = () =>
+    limit(thresholds().too_loud, thresholds().too_loud_baseline);
+  const tooQuiet = () =>
+    limit(thresholds().too_quiet, thresholds().too_quiet_baseline);
+  // Mirrors the bass and voice ranges of the backend
+  const bandLevel = (low: number, high: number) =>
+    10 *
+    Math.log10(
+      spectrum()
+        .filter((band) => band.center >= low && band.center < high)
+        .reduce((sum, band) => sum + 10 ** ((band.level ?? -Infinity) / 10), 0)
+    );
+  const isLufs = () => thresholds().metric.endsWith("Lufs");
+  // LUFS and uncalibrated levels are relative to full scale, calibrated ones in dB SPL.
+  // Silence is -Infinity, which arrives as null
+  const display = (value: number | null) => {
+    const level = (value ?? -Infinity).toFixed(1);
+    if (isLufs()) {
+      return `${level} LUFS`;
+    }
+    if (!measurement().spl) {
+      return `${level} dBFS`;
+    }
+    const unweighted = ["Bass", "Voice"].includes(thresholds().metric);
+    return `${level} dB${unweighted ? "" : measurement().weighting}`;
+  };
+  // Meters show 100 dB, ending at full scale or at the loudest one could bear
+  const floor = (lufs: boolean) => (measurement().spl && !lufs ? 20 : -100);
+  const position = (value: number | null) =>
+    Math.max(0, (value ?? -Infinity) - floor(isLufs()));
+  const [micStatus, setMicStatus] = createSignal<MicStatus>({
+    status: "Connecting",
+  });
+  const [micHealth, setMicHealth] = createSignal<MicHealth | null>(null);
   const unlisten: (() => void)[] = [];
   onMount(async () => {
+    invoke<MicHealth>("mic_health").then(setMicHealth);
+    invoke<WindowLevels[]>("statistics").then(setStatistics);
+    invoke<Dose[]>("exposure").then(setDoses);
     unlisten.push(
       ...(await Promise.all([
         await listen("loudness", (event) => {
           // @ts-ignore  TODO: add zod later
-          setLoudness(event.payload.loudness);
+          setMeasurement(event.payload);
+        }),
+        await listen<Band[]>("spectrum", (event) => {
+          setSpectrum(event.payload);
         }),
         await listen("state", (event) => {
           // @ts-ignore
@@ -26,6 +161,30 @@ function App() {
         await listen("thresholds", (event) => {
           // @ts-ignore
           setThresholds(event.payload);
+          // Band limits start over whenever thresholds change
+          setBreachedBands([]);
+        }),
+        await listen<{ name: string; breached: boolean }>(
+          "band-limit",
+          (event) => {
+            const { name, breached } = event.payload;
+            setBreachedBands((current) => [
+              ...current.filter((band) => band !== name),
+              ...(breached ? [name] : []),
+            ]);
+          }
+        ),
+        await listen<MicStatus>("mic-status", (event) => {
+          setMicStatus(event.payload);
+        }),
+        await listen<MicHealth>("mic-health", (event) => {
+          setMicHealth(event.payload);
+        }),
+        await listen<WindowLevels[]>("statistics", (event) => {
+          setStatistics(event.payload);
+        }),
+        await listen<Dose[]>("exposure", (event) => {
+          setDoses(event.payload);
         }),
       ]))
     );
@@ -39,40 +198,144 @@ function App() {
       <h1>Decibender!</h1>
 
       <p>Current State: {state()}</p>
+      <Show when={breachedBands().length > 0}>
+        <p>Too loud in: {breachedBands().join(", ")}</p>
+      </Show>
+      <p>
+        Mic: {describeMicStatus(micStatus())}
+        <Show when={measurement().gated}> (paused while our sounds play)</Show>
+      </p>
+      <Show when={micHealth()?.problems.length}>
+        <p>
+          Mic problems:{" "}
+          {micHealth()!
+            .problems.map((problem) => describeMicProblem(problem, micHealth()!))
+            .join("; ")}
+          . Not complaining about quiet until it's fixed.
+        </p>
+      </Show>
+      <p>
+        Hearing: {measurement().voice_activity.activity}, speech{" "}
+        {(measurement().voice_activity.speech_ratio * 100).toFixed(0)}% of the
+        last minute
+      </p>
+      <Show when={measurement().direction}>
+        {(direction) => (
+          <p>
+            Loudest from: {describeBearing(direction().bearing)} (
+            {(direction().confidence * 100).toFixed(0)}% sure)
+          </p>
+        )}
+      </Show>
+      <Show when={measurement().noise_floor !== null}>
+        <p>
+          Noise floor: {(measurement().noise_floor ?? -Infinity).toFixed(1)}{" "}
+          {measurement().spl ? `dB${measurement().weighting}` : "dBFS"}
+        </p>
+      </Show>
+      <p>
+        Integrated: {(measurement().lufs.integrated ?? -Infinity).toFixed(1)}{" "}
+        LUFS
+      </p>
+      <Show when={measurement().spl && measurement().weighting === "A"}>
+        <For each={doses()}>
+          {(dose) => (
+            <p>
+              {modelNames[dose.model]} dose: {dose.percent.toFixed(1)}% (
+              {describeTimeToFull(dose)})
+            </p>
+          )}
+        </For>
+      </Show>
+      <Show when={statistics().length > 0}>
+        <table>
+          <thead>
+            <tr>
+              <th>Over</th>
+              <th>L10</th>
+              <th>L50</th>
+              <th>L90</th>
+              <th>Lmax</th>
+              <th>Lmin</th>
+              <th>Leq</th>
+            </tr>
+          </thead>
+          <tbody>
+            <For each={statistics()}>
+              {(levels) => (
+                <tr>
+                  <td>
+                    {windowNames[levels.window]}
+                    <Show
+                      when={levels.measured_seconds < windowSeconds[levels.window]}
+                    >
+                      {" "}
+                      (so far)
+                    </Show>
+                  </td>
+                  <td>{display(levels.l10)}</td>
+                  <td>{display(levels.l50)}</td>
+                  <td>{display(levels.l90)}</td>
+                  <td>{display(levels.lmax)}</td>
+                  <td>{display(levels.lmin)}</td>
+                  <td>{display(levels.leq)}</td>
+                </tr>
+              )}
+            </For>
+          </tbody>
+        </table>
+      </Show>
       <div class="progress-container">
         <progress
-          value={loudness() + 100}
+          value={position(loudness())}
           max={100}
           class="absolute progress-height"
         />
         <progress
-          value={thresholds().too_quiet + 100}
+          value={position(tooQuiet())}
           max={100}
           class="absolute threshold progress-height"
         />
         <progress
-          value={thresholds().too_quiet + thresholds().grace + 100}
+          value={position(tooQuiet() + thresholds().grace)}
           max={100}
           class="absolute progress-height grace"
         />
         <progress
-          value={-thresholds().too_loud}
+          value={100 - position(tooLoud())}
           max={100}
           class="absolute threshold progress-height rotate-180"
         />
         <progress
-          value={-thresholds().too_loud + thresholds().grace}
+          value={100 - position(tooLoud() - thresholds().grace)}
           max={100}
           class="absolute progress-height grace rotate-180"
         />
         <span class="absolute left">
-          {(thresholds().too_quiet + 100).toFixed(1)} dB
+          {display(tooQuiet())}
+        </span>
+        <span class="absolute center">
+          {loudness() === null ? "Measuring..." : display(loudness())}
         </span>
-        <span class="absolute center">{(loudness() + 100).toFixed(1)} dB</span>
         <span class="absolute right">
-          {(thresholds().too_loud + 100).toFixed(1)} dB
+          {display(tooLoud())}
         </span>
       </div>
+      <div class="spectrum">
+        <For each={spectrum()}>
+          {(band) => (
+            <div
+              class="band"
+              style={{
+                height: `${Math.max(0, (band.level ?? -Infinity) - floor(false))}%`,
+              }}
+              title={`${formatFrequency(band.center)}: ${(
+                band.level ?? -Infinity
+              ).toFixed(1)} ${measurement().spl ? "dB" : "dBFS"}`}
+            />
+          )}
+        </For>
+      </div>
     </main>
   );
 }
@@ -0,0 +1,106 @@
+import { invoke } from "@tauri-apps/api/tauri";
+import { createSignal, For, onMount, Show } from "solid-js";
+
+// Sound level calibrators play a 1 kHz tone at one of these
+const CALIBRATOR_LEVELS = [94, 114];
+
+function Calibration(props: { device: string | null }) {
+  const [referenceLevel, setReferenceLevel] = createSignal(94);
+  const [measuring, setMeasuring] = createSignal(false);
+  const [message, setMessage] = createSignal("");
+  const [calibrations, setCalibrations] = createSignal<Record<string, number>>(
+    {}
+  );
+  const refresh = async () =>
+    setCalibrations(await invoke<Record<string, number>>("list_calibrations"));
+  onMount(refresh);
+
+  const calibrate = async () => {
+    setMeasuring(true);
+    setMessage("Measuring, keep the reference steady for 5 seconds...");
+    try {
+      const offset = await invoke<number>("calibrate", {
+        referenceLevel: referenceLevel(),
+      });
+      setMessage(`Calibrated, offset ${offset.toFixed(1)} dB`);
+    } catch (e) {
+      setMessage(`Calibration failed: ${e}`);
+    }
+    setMeasuring(false);
+    await refresh();
+  };
+
+  return (
+    <>
+      <h2>Calibration</h2>
+      <p>
+        Hold a calibrator to the microphone, or put a phone running an SPL
+        meter app next to it and enter its reading, using the same weighting.
+      </p>
+      <div class="grid">
+        <label>
+          Reference:
+          <select
+            name="reference"
+            value={
+              CALIBRATOR_LEVELS.includes(referenceLevel())
+                ? referenceLevel()
+                : "custom"
+            }
+            onChange={(e) => {
+              if (e.target.value !== "custom") {
+                setReferenceLevel(Number(e.target.value));
+              }
+            }}
+          >
+            <For each={CALIBRATOR_LEVELS}>
+              {(level) => <option value={level}>{level} dB calibrator</option>}
+            </For>
+            <option value="custom">Phone app reading</option>
+          </select>
+        </label>
+        <label>
+          Reference Level (dB SPL):
+          <input
+            type="number"
+            name="referenceLevel"
+            value={referenceLevel()}
+            onChange={(e) => setReferenceLevel(Number(e.target.value))}
+            step={0.1}
+            min={20}
+            max={140}
+          />
+        </label>
+        <button
+          disabled={measuring() || props.device === null}
+          onClick={calibrate}
+        >
+          Calibrate {props.device ?? ""}
+        </button>
+      </div>
+      <Show when={message()}>
+        <p>{message()}</p>
+      </Show>
+      <For each={Object.entries(calibrations())}>
+        {([device, offset]) => (
+          <div class="grid">
+            <span>
+              {device}: {offset.toFixed(1)} dB
+            </span>
+            <button
+              class="secondary"
+              onClick={async () => {
+                await invoke("clear_calibration", { device });
+                await refresh();
+              }}
+            >
+              Clear
+            </button>
+          </div>
+        )}
+      </For>
+    </>
+  );
+}
+
+export default Calibration;
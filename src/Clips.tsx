@@ -0,0 +1,132 @@
+import { listen } from "@tauri-apps/api/event";
+import { invoke } from "@tauri-apps/api/tauri";
+import { createSignal, For, onCleanup, onMount, Show } from "solid-js";
+
+type ClipSettings = {
+  enabled: boolean;
+  pre_seconds: number;
+  post_seconds: number;
+  max_clips: number;
+};
+
+type ClipInfo = {
+  id: string;
+  recorded_at_ms: number;
+  device: string | null;
+  metric: string;
+  level: number;
+  too_loud: number | null;
+  side: "Left" | "Center" | "Right" | null;
+  seconds: number;
+};
+
+function Clips() {
+  const [settings, setSettings] = createSignal<ClipSettings | null>(null);
+  const [clips, setClips] = createSignal<ClipInfo[]>([]);
+  const refresh = async () => setClips(await invoke<ClipInfo[]>("list_clips"));
+  let unlisten = () => {};
+  onMount(async () => {
+    setSettings(await invoke<ClipSettings>("clip_settings"));
+    await refresh();
+    unlisten = await listen<ClipInfo>("clip", () => refresh());
+  });
+  onCleanup(() => unlisten());
+
+  const updateSettings = async (changes: Partial<ClipSettings>) => {
+    const current = settings();
+    if (!current) return;
+    const updated = { ...current, ...changes };
+    setSettings(updated);
+    await invoke("set_clip_settings", { settings: updated });
+    // A lower limit deletes the oldest clips right away
+    await refresh();
+  };
+  const deleteClip = async (id: string) => {
+    await invoke("delete_clip", { id });
+    await refresh();
+  };
+
+  return (
+    <>
+      <h2>Incident Clips</h2>
+      <Show when={settings()}>
+        {(settings) => (
+          <div class="grid">
+            <label>
+              <input
+                type="checkbox"
+                role="switch"
+                checked={settings().enabled}
+                onChange={(e) =>
+                  updateSettings({ enabled: e.target.checked })
+                }
+              />
+              Record when it gets too loud
+            </label>
+            <label>
+              Seconds Before:
+              <input
+                type="number"
+                value={settings().pre_seconds}
+                onChange={(e) =>
+                  updateSettings({ pre_seconds: Number(e.target.value) })
+                }
+                min={0}
+                max={60}
+              />
+            </label>
+            <label>
+              Seconds After:
+              <input
+                type="number"
+                value={settings().post_seconds}
+                onChange={(e) =>
+                  updateSettings({ post_seconds: Number(e.target.value) })
+                }
+                min={0}
+                max={60}
+              />
+            </label>
+            <label>
+              Keep At Most:
+              <input
+                type="number"
+                value={settings().max_clips}
+                onChange={(e) =>
+                  updateSettings({ max_clips: Number(e.target.value) })
+                }
+                min={0}
+              />
+            </label>
+          </div>
+        )}
+      </Show>
+      <For each={clips()}>
+        {(clip) => (
+          <div class="grid">
+            <span>
+              {new Date(clip.recorded_at_ms).toLocaleString()},{" "}
+              {clip.level.toFixed(1)} {clip.metric}
+              {clip.too_loud === null
+                ? ""
+                : ` (limit ${clip.too_loud.toFixed(1)})`}
+              {clip.side === null ? "" : `, from the ${clip.side.toLowerCase()}`}
+              , {clip.seconds.toFixed(0)}s
+            </span>
+            <button
+              class="secondary"
+              onClick={() => invoke("play_clip", { id: clip.id })}
+            >
+              Play
+            </button>
+            <button class="secondary" onClick={() => deleteClip(clip.id)}>
+              Delete
+            </button>
+          </div>
+        )}
+      </For>
+    </>
+  );
+}
+
+export default Clips;
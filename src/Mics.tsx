@@ -0,0 +1,199 @@
+import { listen } from "@tauri-apps/api/event";
+import { invoke } from "@tauri-apps/api/tauri";
+import { createSignal, For, onCleanup, onMount } from "solid-js";
+
+type ExtraMic = {
+  name: string;
+  enabled: boolean;
+};
+
+// Where an extra microphone is measured differently from the main one, null for the same
+export type MicSettings = {
+  weighting: string | null;
+  // Only turning the main microphone's filters off is offered
+  filters: [] | null;
+};
+
+type MicLevel = {
+  mic: number;
+  // Silence is -Infinity, which arrives as null
+  loudness: number | null;
+  // Calibrated by the device's own offset
+  spl: boolean;
+};
+
+function Mics(props: {
+  deviceNames: string[];
+  aggregation: string;
+  weights: number[];
+  onAggregationChange: (aggregation: string) => void;
+  // By microphone, the main one first
+  onWeightsChange: (weights: number[]) => void;
+  // By extra microphone
+  micSettings: MicSettings[];
+  onMicSettingsChange: (micSettings: MicSettings[]) => void;
+}) {
+  const [extraMics, setExtraMics] = createSignal<ExtraMic[]>([]);
+  const [levels, setLevels] = createSignal<MicLevel[]>([]);
+  let unlisten = () => {};
+  onMount(async () => {
+    unlisten = await listen<MicLevel[]>("mic-levels", (event) => {
+      setLevels(event.payload);
+    });
+  });
+  onCleanup(() => unlisten());
+
+  const updateMics = (mics: ExtraMic[]) => {
+    setExtraMics(mics);
+    invoke("set_extra_mics", { mics });
+  };
+  const weight = (mic: number) => props.weights[mic] ?? 1;
+  const setWeight = (mic: number, value: number) =>
+    props.onWeightsChange(
+      Array.from({ length: extraMics().length + 1 }, (_, i) =>
+        i === mic ? value : weight(i)
+      )
+    );
+  const settings = (index: number): MicSettings =>
+    props.micSettings[index] ?? { weighting: null, filters: null };
+  const setSettings = (index: number, changes: Partial<MicSettings>) =>
+    props.onMicSettingsChange(
+      Array.from({ length: extraMics().length }, (_, i) =>
+        i === index ? { ...settings(i), ...changes } : settings(i)
+      )
+    );
+  const level = (mic: number) => {
+    const level = levels().find((level) => level.mic === mic);
+    return level === undefined
+      ? "-"
+      : `${(level.loudness ?? -Infinity).toFixed(1)} ${
+          level.spl ? "dB SPL" : "dBFS"
+        }`;
+  };
+
+  return (
+    <>
+      <h2>Microphones</h2>
+      <div class="grid">
+        <label>
+          Combine By:
+          <select
+            name="aggregation"
+            value={props.aggregation}
+            onChange={(e) => props.onAggregationChange(e.target.value)}
+          >
+            <option value="Max">Loudest</option>
+            <option value="MeanPower">Mean power</option>
+            <option value="Median">Median</option>
+            <option value="Weighted">Weighted mean power</option>
+          </select>
+        </label>
+        <label>
+          Main Mic Weight:
+          <input
+            type="number"
+            value={weight(0)}
+            onChange={(e) => setWeight(0, Number(e.target.value))}
+            step={0.1}
+            min={0}
+          />
+        </label>
+        <span>Main Mic: {level(0)}</span>
+      </div>
+      <For each={extraMics()}>
+        {(mic, index) => (
+          <div class="grid">
+            <label>
+              Mic {index() + 1}:
+              <select
+                value={mic.name}
+                onChange={(e) =>
+                  updateMics(
+                    extraMics().map((m, i) =>
+                      i === index() ? { ...m, name: e.target.value } : m
+                    )
+                  )
+                }
+              >
+                <For each={props.deviceNames}>
+                  {(name) => <option value={name}>{name}</option>}
+                </For>
+              </select>
+            </label>
+            <label>
+              <input
+                type="checkbox"
+                checked={mic.enabled}
+                onChange={(e) =>
+                  updateMics(
+                    extraMics().map((m, i) =>
+                      i === index() ? { ...m, enabled: e.target.checked } : m
+                    )
+                  )
+                }
+              />
+              Enabled
+            </label>
+            <label>
+              Weight:
+              <input
+                type="number"
+                value={weight(index() + 1)}
+                onChange={(e) =>
+                  setWeight(index() + 1, Number(e.target.value))
+                }
+                step={0.1}
+                min={0}
+              />
+            </label>
+            <label>
+              Weighting:
+              <select
+                value={settings(index()).weighting ?? ""}
+                onChange={(e) =>
+                  setSettings(index(), {
+                    weighting: e.target.value === "" ? null : e.target.value,
+                  })
+                }
+              >
+                <option value="">Same as main</option>
+                <option value="A">A (dBA)</option>
+                <option value="C">C (dBC)</option>
+                <option value="Z">Z (flat)</option>
+              </select>
+            </label>
+            <label>
+              Filters:
+              <select
+                value={settings(index()).filters === null ? "main" : "none"}
+                onChange={(e) =>
+                  setSettings(index(), {
+                    filters: e.target.value === "main" ? null : [],
+                  })
+                }
+              >
+                <option value="main">Same as main</option>
+                <option value="none">None</option>
+              </select>
+            </label>
+            <span>{level(index() + 1)}</span>
+          </div>
+        )}
+      </For>
+      <button
+        class="secondary"
+        disabled={props.deviceNames.length === 0}
+        onClick={() =>
+          updateMics([
+            ...extraMics(),
+            { name: props.deviceNames[0], enabled: true },
+          ])
+        }
+      >
+        Add Mic
+      </button>
+    </>
+  );
+}
+
+export default Mics;
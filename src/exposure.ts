@@ -0,0 +1,26 @@
+export type ExposureModel = "Niosh" | "Osha";
+
+export type ExposureLimit = {
+  model: ExposureModel;
+  dose_percent: number;
+};
+
+export type Dose = {
+  model: ExposureModel;
+  percent: number;
+  seconds_to_full: number | null;
+};
+
+export const modelNames: Record<ExposureModel, string> = {
+  Niosh: "NIOSH (85 dBA, 3 dB)",
+  Osha: "OSHA (90 dBA, 5 dB)",
+};
+
+export const describeTimeToFull = (dose: Dose) => {
+  if (dose.seconds_to_full === null) return "not rising";
+  if (dose.seconds_to_full === 0) return "reached";
+  const minutes = Math.round(dose.seconds_to_full / 60);
+  return minutes >= 60
+    ? `${Math.floor(minutes / 60)} h ${minutes % 60} min to 100%`
+    : `${minutes} min to 100%`;
+};
@@ -0,0 +1,56 @@
+export type MicStatus =
+  | { status: "Connecting" }
+  | { status: "Connected"; device: string }
+  | {
+      status: "Disconnected";
+      error: string;
+      attempt: number;
+      retry_in_ms: number;
+    }
+  | { status: "Finished"; error: string | null };
+
+export function describeMicStatus(micStatus: MicStatus) {
+  switch (micStatus.status) {
+    case "Connecting":
+      return "Connecting...";
+    case "Connected":
+      return `Listening on ${micStatus.device}`;
+    case "Disconnected":
+      return `Disconnected (${micStatus.error}), retry #${micStatus.attempt} in ${(
+        micStatus.retry_in_ms / 1000
+      ).toFixed(1)}s`;
+    case "Finished":
+      return micStatus.error === null
+        ? "Finished"
+        : `Stopped (${micStatus.error})`;
+  }
+}
+
+export type MicProblem =
+  | "Clipping"
+  | "DeadInput"
+  | "DcOffset"
+  | "InvalidSamples";
+
+export type MicHealth = {
+  clipping_ratio: number;
+  silent_seconds: number;
+  dc_offset: number;
+  invalid_samples: number;
+  problems: MicProblem[];
+};
+
+export function describeMicProblem(problem: MicProblem, health: MicHealth) {
+  switch (problem) {
+    case "Clipping":
+      return `clipping (${(health.clipping_ratio * 100).toFixed(
+        2
+      )}% of samples), turn the gain down`;
+    case "DeadInput":
+      return `nothing but silence for ${health.silent_seconds}s, is it muted or unplugged?`;
+    case "DcOffset":
+      return `DC offset of ${health.dc_offset.toFixed(3)}`;
+    case "InvalidSamples":
+      return `${health.invalid_samples} invalid samples`;
+  }
+}
@@ -0,0 +1,42 @@
+export type StatisticalLevel = "L10" | "L50" | "L90" | "Lmax" | "Lmin" | "Leq";
+
+export type StatisticsWindow = "OneMinute" | "FifteenMinutes" | "OneHour";
+
+export type Statistic = {
+  level: StatisticalLevel;
+  window: StatisticsWindow;
+};
+
+export type WindowLevels = {
+  window: StatisticsWindow;
+  measured_seconds: number;
+  l10: number;
+  l50: number;
+  l90: number;
+  lmax: number;
+  lmin: number;
+  // Silence is -Infinity, which arrives as null
+  leq: number | null;
+};
+
+export const windowNames: Record<StatisticsWindow, string> = {
+  OneMinute: "1 min",
+  FifteenMinutes: "15 min",
+  OneHour: "1 h",
+};
+
+export const windowSeconds: Record<StatisticsWindow, number> = {
+  OneMinute: 60,
+  FifteenMinutes: 15 * 60,
+  OneHour: 60 * 60,
+};
+
+export const statisticOf = (levels: WindowLevels, level: StatisticalLevel) =>
+  ({
+    L10: levels.l10,
+    L50: levels.l50,
+    L90: levels.l90,
+    Lmax: levels.lmax,
+    Lmin: levels.lmin,
+    Leq: levels.leq ?? -Infinity,
+  })[level];